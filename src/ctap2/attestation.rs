@@ -0,0 +1,54 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The CTAP2 `attestationObject` returned by `authenticatorMakeCredential`.
+
+/// A COSE-encoded credential public key, kept as its raw wire bytes; the
+/// manager only ever copies these through to the legacy U2F registration
+/// response, so there's no need to parse the COSE structure here.
+#[derive(Debug, Clone)]
+pub struct CredentialPublicKey {
+    pub bytes: Vec<u8>,
+}
+
+/// The credential identifier and public key minted by `MakeCredentials`.
+#[derive(Debug, Clone)]
+pub struct CredentialData {
+    pub credential_id: Vec<u8>,
+    pub credential_public_key: CredentialPublicKey,
+}
+
+/// The `authData` structure embedded in every attestation object and
+/// assertion.
+#[derive(Debug, Clone)]
+pub struct AuthenticatorData {
+    pub rp_id_hash: [u8; 32],
+    pub flags: u8,
+    pub counter: u32,
+    pub credential_data: Option<CredentialData>,
+}
+
+/// The `fido-u2f` attestation statement format: an X.509 certificate chain
+/// and a signature over the registration data, exactly as CTAP1 `U2F_REGISTER`
+/// produces.
+#[derive(Debug, Clone)]
+pub struct FidoU2FAttestationStatement {
+    pub attestation_cert: Vec<Vec<u8>>,
+    pub sig: Vec<u8>,
+}
+
+/// The attestation statement formats this crate knows how to translate back
+/// into a legacy U2F registration response.
+#[derive(Debug, Clone)]
+pub enum AttestationStatement {
+    FidoU2F(FidoU2FAttestationStatement),
+    None,
+}
+
+/// The full `attestationObject` returned by `authenticatorMakeCredential`.
+#[derive(Debug, Clone)]
+pub struct AttestationObject {
+    pub auth_data: AuthenticatorData,
+    pub att_statement: AttestationStatement,
+}
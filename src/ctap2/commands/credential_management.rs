@@ -0,0 +1,27 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The CTAP2 `authenticatorCredentialManagement` subcommands this crate
+//! supports: enumerating and deleting discoverable credentials.
+
+use crate::ctap2::server::{PublicKeyCredentialDescriptor, RelyingParty, User};
+
+/// An `authenticatorCredentialManagement` request.
+#[derive(Debug, Clone)]
+pub enum CredentialManagementRequest {
+    /// `enumerateRPsBegin`/`enumerateRPsGetNextRP` followed by
+    /// `enumerateCredentialsBegin`/`enumerateCredentialsGetNextCredential`
+    /// for each relying party, flattened into a single request/response
+    /// round trip.
+    EnumerateCredentials,
+    /// `deleteCredential`.
+    DeleteCredential(PublicKeyCredentialDescriptor),
+}
+
+/// An `authenticatorCredentialManagement` response.
+#[derive(Debug, Clone)]
+pub enum CredentialManagementResponse {
+    Credentials(Vec<(RelyingParty, Vec<User>)>),
+    Deleted,
+}
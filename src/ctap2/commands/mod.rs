@@ -0,0 +1,147 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! CTAP2 command request/response types: `authenticatorMakeCredential`,
+//! `authenticatorGetAssertion`, and the PIN/UV and credential-management
+//! subcommands built on top of them.
+
+pub mod credential_management;
+pub mod pin_protocol;
+
+use crate::ctap2::attestation::AuthenticatorData;
+use crate::ctap2::server::{
+    PublicKeyCredentialDescriptor, PublicKeyCredentialParameters, RelyingParty, User,
+};
+use crate::manager::HmacSecretSalts;
+
+/// A user-supplied clientPIN. `Debug` redacts the value so it never ends up
+/// in a log line by accident.
+#[derive(Clone)]
+pub struct Pin(String);
+
+impl Pin {
+    pub fn new(pin: impl Into<String>) -> Self {
+        Pin(pin.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Pin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Pin(..)")
+    }
+}
+
+/// Authenticator-side options carried in `authenticatorMakeCredential`'s
+/// `options` map and `extensions` map.
+#[derive(Debug, Clone, Default)]
+pub struct MakeCredentialsOptions {
+    /// `options.uv`: require user verification, not just presence.
+    pub user_validation: bool,
+    /// `options.rk`: create a discoverable (resident) credential.
+    pub resident_key: bool,
+    /// `extensions.hmac-secret` (registration): request the extension be
+    /// enabled for this credential.
+    pub hmac_secret: bool,
+    /// `extensions.hmac-secret` (assertion): the salt(s) to encrypt and send
+    /// so the authenticator can derive and return the corresponding
+    /// secret(s).
+    pub hmac_secret_salts: Option<HmacSecretSalts>,
+}
+
+/// An `authenticatorMakeCredential` request.
+#[derive(Debug, Clone)]
+pub struct MakeCredentials {
+    pub client_data_hash: [u8; 32],
+    pub relying_party: RelyingParty,
+    pub user: Option<User>,
+    pub pub_cred_params: Vec<PublicKeyCredentialParameters>,
+    pub exclude_list: Vec<PublicKeyCredentialDescriptor>,
+    pub options: Option<MakeCredentialsOptions>,
+    pub pin: Option<Pin>,
+}
+
+impl MakeCredentials {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        client_data_hash: [u8; 32],
+        relying_party: RelyingParty,
+        user: Option<User>,
+        pub_cred_params: Vec<PublicKeyCredentialParameters>,
+        exclude_list: Vec<PublicKeyCredentialDescriptor>,
+        options: Option<MakeCredentialsOptions>,
+        pin: Option<Pin>,
+    ) -> Self {
+        MakeCredentials {
+            client_data_hash,
+            relying_party,
+            user,
+            pub_cred_params,
+            exclude_list,
+            options,
+            pin,
+        }
+    }
+}
+
+/// An `authenticatorGetAssertion` request.
+#[derive(Debug, Clone)]
+pub struct GetAssertion {
+    pub client_data_hash: [u8; 32],
+    pub relying_party: RelyingParty,
+    pub allow_list: Vec<PublicKeyCredentialDescriptor>,
+    pub options: Option<MakeCredentialsOptions>,
+    pub pin: Option<Pin>,
+}
+
+impl GetAssertion {
+    pub fn new(
+        client_data_hash: [u8; 32],
+        relying_party: RelyingParty,
+        allow_list: Vec<PublicKeyCredentialDescriptor>,
+        options: Option<MakeCredentialsOptions>,
+        pin: Option<Pin>,
+    ) -> Self {
+        GetAssertion {
+            client_data_hash,
+            relying_party,
+            allow_list,
+            options,
+            pin,
+        }
+    }
+}
+
+/// The result of an `authenticatorGetAssertion`, including the decrypted
+/// `hmac-secret` output if the extension was requested.
+#[derive(Debug, Clone)]
+pub struct AssertionObject {
+    pub credential_id: Vec<u8>,
+    pub auth_data: AuthenticatorData,
+    pub signature: Vec<u8>,
+    pub hmac_secret_output: Option<HmacSecretOutput>,
+}
+
+impl AssertionObject {
+    pub fn credential_id(&self) -> Vec<u8> {
+        self.credential_id.clone()
+    }
+
+    /// The signature bytes, as `U2FManager::sign()` hands them back in its
+    /// legacy `(relying_party_id, credential_id, signature)` result tuple.
+    pub fn u2f_sign_data(&self) -> Vec<u8> {
+        self.signature.clone()
+    }
+}
+
+/// The decrypted `hmac-secret` extension output: one secret per salt that
+/// was requested.
+#[derive(Debug, Clone)]
+pub enum HmacSecretOutput {
+    One([u8; 32]),
+    Two([u8; 32], [u8; 32]),
+}
@@ -0,0 +1,187 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! CTAP2 `pinUvAuthProtocolOne`: the ECDH key-agreement, AES-256-CBC
+//! encryption, and HMAC-SHA256 authentication used to protect PINs and
+//! PIN/UV auth tokens in transit, and to authenticate commands with a
+//! `pinUvAuthParam`.
+
+use aes::cipher::block_padding::NoPadding;
+use aes::cipher::{BlockModeDecrypt, BlockModeEncrypt, KeyIvInit};
+use aes::Aes256;
+use hmac::{Hmac, KeyInit, Mac};
+use p256::ecdh::EphemeralSecret;
+use p256::elliptic_curve::Generate;
+use p256::{PublicKey, Sec1Point};
+use sha2::{Digest, Sha256};
+
+use crate::{Error, PinError};
+
+type Aes256CbcEnc = cbc::Encryptor<Aes256>;
+type Aes256CbcDec = cbc::Decryptor<Aes256>;
+type HmacSha256 = Hmac<Sha256>;
+
+/// `pinUvAuthProtocolOne` always encrypts/decrypts with an all-zero IV; the
+/// shared secret is never reused across messages, so a fixed IV doesn't
+/// weaken CBC here.
+const ZERO_IV: [u8; 16] = [0u8; 16];
+
+/// One side's ephemeral P-256 keypair for the `getKeyAgreement` exchange.
+pub struct KeyAgreement(EphemeralSecret);
+
+impl KeyAgreement {
+    /// Generates a fresh ephemeral keypair. A new one must be used for every
+    /// PIN/UV-auth-token request; reusing one would let two requests derive
+    /// the same shared secret.
+    pub fn generate() -> Self {
+        KeyAgreement(EphemeralSecret::generate())
+    }
+
+    /// This side's public key, as uncompressed SEC1 bytes, to be sent to (or
+    /// received from) the peer.
+    pub fn public_key_bytes(&self) -> Vec<u8> {
+        Sec1Point::from(self.0.public_key()).as_bytes().to_vec()
+    }
+
+    /// Performs the ECDH exchange with the peer's SEC1-encoded public key and
+    /// derives the `pinUvAuthProtocolOne` shared secret: SHA-256 of the
+    /// resulting point's x-coordinate.
+    pub fn shared_secret(&self, peer_public_key: &[u8]) -> Result<SharedSecret, Error> {
+        let peer = PublicKey::from_sec1_bytes(peer_public_key).map_err(|_| Error::Unknown)?;
+        let shared = self.0.diffie_hellman(&peer);
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&Sha256::digest(shared.raw_secret_bytes()));
+        Ok(SharedSecret(key))
+    }
+}
+
+/// The `pinUvAuthProtocolOne` shared secret derived from an ECDH key
+/// agreement. Used to encrypt/decrypt PIN material and to authenticate
+/// `pinUvAuthParam`s.
+#[derive(Clone)]
+pub struct SharedSecret([u8; 32]);
+
+impl SharedSecret {
+    /// Wraps a raw 32-byte key as a `SharedSecret`. Used both for an
+    /// ECDH-derived key-agreement secret and for a `pinUvAuthToken` itself,
+    /// which is authenticated and encrypted/decrypted the same way a
+    /// key-agreement secret is.
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        SharedSecret(bytes)
+    }
+
+    /// AES-256-CBC encrypts `plaintext` under a zero IV. `plaintext` must
+    /// already be a multiple of the AES block size (16 bytes); the protocol
+    /// never pads anything except the PIN itself, which callers pad via
+    /// [`pad_pin`] before calling this.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+        Aes256CbcEnc::new(&self.0.into(), &ZERO_IV.into()).encrypt_padded_vec::<NoPadding>(plaintext)
+    }
+
+    /// Decrypts a ciphertext produced by [`SharedSecret::encrypt`].
+    pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        Aes256CbcDec::new(&self.0.into(), &ZERO_IV.into())
+            .decrypt_padded_vec::<NoPadding>(ciphertext)
+            .map_err(|_| Error::Unknown)
+    }
+
+    /// Computes `pinUvAuthParam = HMAC-SHA256(sharedSecret, message)[..16]`,
+    /// e.g. over a `clientDataHash` or a new/current PIN hash.
+    pub fn authenticate(&self, message: &[u8]) -> [u8; 16] {
+        let mut mac = HmacSha256::new_from_slice(&self.0).expect("HMAC accepts any key length");
+        mac.update(message);
+        let mut out = [0u8; 16];
+        out.copy_from_slice(&mac.finalize().into_bytes()[..16]);
+        out
+    }
+
+    /// Verifies a `pinUvAuthParam` produced by [`SharedSecret::authenticate`].
+    pub fn verify(&self, message: &[u8], pin_uv_auth_param: &[u8; 16]) -> bool {
+        self.authenticate(message) == *pin_uv_auth_param
+    }
+}
+
+/// `LEFT(SHA-256(pin), 16)`, the `pinHash` sent to the authenticator when
+/// unlocking a PIN/UV auth token.
+pub fn pin_hash(pin: &str) -> [u8; 16] {
+    let mut hash = [0u8; 16];
+    hash.copy_from_slice(&Sha256::digest(pin.as_bytes())[..16]);
+    hash
+}
+
+/// Right-pads `pin` with zero bytes to the fixed 64-byte length the
+/// `setPIN`/`changePIN` subcommands encrypt, per the CTAP2 spec's minimum
+/// and maximum PIN length rules (4-63 UTF-8 bytes, excluding the trailing
+/// NUL padding).
+pub fn pad_pin(pin: &str) -> Result<[u8; 64], Error> {
+    let bytes = pin.as_bytes();
+    if bytes.len() < 4 {
+        return Err(Error::Pin(PinError::MinPinLength));
+    }
+    if bytes.len() > 63 {
+        return Err(Error::Unknown);
+    }
+    let mut padded = [0u8; 64];
+    padded[..bytes.len()].copy_from_slice(bytes);
+    Ok(padded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ecdh_agreement_is_symmetric() {
+        let alice = KeyAgreement::generate();
+        let bob = KeyAgreement::generate();
+
+        let alice_secret = alice.shared_secret(&bob.public_key_bytes()).unwrap();
+        let bob_secret = bob.shared_secret(&alice.public_key_bytes()).unwrap();
+
+        assert_eq!(alice_secret.0, bob_secret.0);
+    }
+
+    #[test]
+    fn encrypt_decrypt_round_trips() {
+        let alice = KeyAgreement::generate();
+        let bob = KeyAgreement::generate();
+        let secret = alice.shared_secret(&bob.public_key_bytes()).unwrap();
+
+        let plaintext = pin_hash("1234");
+        let ciphertext = secret.encrypt(&plaintext);
+        assert_ne!(ciphertext, plaintext);
+        assert_eq!(secret.decrypt(&ciphertext).unwrap(), plaintext);
+    }
+
+    #[test]
+    fn pin_uv_auth_param_round_trips() {
+        let alice = KeyAgreement::generate();
+        let bob = KeyAgreement::generate();
+        let secret = alice.shared_secret(&bob.public_key_bytes()).unwrap();
+
+        let client_data_hash = [7u8; 32];
+        let param = secret.authenticate(&client_data_hash);
+        assert!(secret.verify(&client_data_hash, &param));
+        assert!(!secret.verify(&[0u8; 32], &param));
+    }
+
+    #[test]
+    fn pad_pin_rejects_out_of_range_lengths() {
+        assert!(pad_pin("").is_err());
+        assert!(pad_pin(&"a".repeat(64)).is_err());
+        let padded = pad_pin("1234").unwrap();
+        assert_eq!(&padded[..4], b"1234");
+        assert!(padded[4..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn pad_pin_rejects_pins_shorter_than_four_bytes() {
+        for pin in ["", "1", "12", "123"] {
+            assert!(matches!(
+                pad_pin(pin),
+                Err(Error::Pin(PinError::MinPinLength))
+            ));
+        }
+    }
+}
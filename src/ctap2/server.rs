@@ -0,0 +1,93 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Relying-party-facing types: the entities a CTAP2 `MakeCredentials`/
+//! `GetAssertion` command is built around.
+
+use sha2::{Digest, Sha256};
+
+/// A WebAuthn relying party, identified by its RP ID (typically a domain).
+/// The authenticator never sees the RP ID itself, only its SHA-256 hash.
+#[derive(Debug, Clone)]
+pub struct RelyingParty {
+    pub id: String,
+    pub hash: [u8; 32],
+}
+
+impl RelyingParty {
+    pub fn new(id: &str) -> Self {
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&Sha256::digest(id.as_bytes()));
+        RelyingParty {
+            id: id.to_string(),
+            hash,
+        }
+    }
+
+}
+
+/// One entry of a `pubKeyCredParams` list: a COSE algorithm identifier for
+/// a credential public key type the relying party is willing to accept.
+/// `-7` is ES256 (P-256 + SHA-256), the only algorithm CTAP2 authenticators
+/// are required to support.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublicKeyCredentialParameters {
+    pub alg: i64,
+}
+
+impl PublicKeyCredentialParameters {
+    pub const ES256: PublicKeyCredentialParameters = PublicKeyCredentialParameters { alg: -7 };
+}
+
+/// The WebAuthn `user` entity, required to create a discoverable/resident
+/// credential.
+#[derive(Debug, Clone)]
+pub struct User {
+    pub id: Vec<u8>,
+    pub name: String,
+    pub display_name: String,
+}
+
+/// One entry of an `excludeList`/`allowList`: a credential identifier the
+/// authenticator should recognize, without the transport hints a full
+/// `KeyHandle` carries.
+#[derive(Debug, Clone)]
+pub struct PublicKeyCredentialDescriptor {
+    pub id: Vec<u8>,
+}
+
+impl From<&crate::KeyHandle> for PublicKeyCredentialDescriptor {
+    fn from(key_handle: &crate::KeyHandle) -> Self {
+        PublicKeyCredentialDescriptor {
+            id: key_handle.credential.clone(),
+        }
+    }
+}
+
+impl From<crate::KeyHandle> for PublicKeyCredentialDescriptor {
+    fn from(key_handle: crate::KeyHandle) -> Self {
+        PublicKeyCredentialDescriptor {
+            id: key_handle.credential,
+        }
+    }
+}
+
+/// Whether the relying party wants a discoverable (resident) credential.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResidentKeyRequirement {
+    #[default]
+    Discouraged,
+    Preferred,
+    Required,
+}
+
+/// Whether the relying party wants the user verified (PIN/biometric), as
+/// opposed to merely present (touch).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UserVerificationRequirement {
+    Discouraged,
+    #[default]
+    Preferred,
+    Required,
+}
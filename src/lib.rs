@@ -0,0 +1,97 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A cross-platform library for interacting with CTAP1/U2F and CTAP2/WebAuthn
+//! security keys.
+//!
+//! This build only ships an in-process, software-emulated authenticator
+//! (see [`transport::platform::SoftwareToken`]) rather than a real USB/NFC/BLE
+//! HID transport: there is no CBOR codec or hardware I/O layer wired up yet,
+//! so `U2FManager` always drives that software token, never an actual
+//! security key. The rest of the crate — `StateMachine`'s device-selection
+//! race, the `pinUvAuthProtocolOne` handshake, the `hmac-secret` extension,
+//! credential management — is implemented for real against the
+//! [`transport::Device`] trait, so a real hardware transport is a matter of
+//! implementing that trait, not rewriting any of this logic.
+
+extern crate aes;
+extern crate cbc;
+extern crate elliptic_curve;
+extern crate hmac;
+extern crate p256;
+extern crate rand;
+extern crate sha2;
+
+pub mod consts;
+pub mod ctap;
+pub mod ctap2;
+mod manager;
+mod runloop;
+mod statemachine;
+pub mod transport;
+mod util;
+
+#[allow(deprecated)]
+pub use manager::U2FManager;
+pub use manager::{
+    BlinkResult, HmacSecretSalts, PinError, RegisterArgs, RegisterExtensions, SignArgs,
+    SignExtensions, StatusUpdate,
+};
+
+/// Errors returned by this crate's public API.
+#[derive(Debug)]
+pub enum Error {
+    Unknown,
+    NotSupported,
+    NotAllowed,
+    DeviceError(String),
+    Pin(PinError),
+    /// No device was selected (and, for `register()`/`sign()`, touched)
+    /// before the operation's timeout elapsed.
+    Timeout,
+}
+
+/// A handle to a previously registered credential, as returned by
+/// `register()` and consumed by `sign()`'s exclude/allow lists.
+#[derive(Debug, Clone)]
+pub struct KeyHandle {
+    pub credential: Vec<u8>,
+    pub transports: Vec<Transport>,
+}
+
+impl KeyHandle {
+    pub fn new(credential: Vec<u8>) -> Self {
+        KeyHandle {
+            credential,
+            transports: Vec::new(),
+        }
+    }
+}
+
+/// Transports a credential may be reachable over, per the WebAuthn spec.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Usb,
+    Nfc,
+    Ble,
+    Internal,
+}
+
+/// A WebAuthn/U2F application identifier, as a raw SHA-256 hash or URL.
+pub type AppId = Vec<u8>;
+
+/// The raw U2F-style registration response `U2FManager::register()` hands
+/// back to its callback.
+pub type RegisterResult = Vec<u8>;
+
+/// `(relying_party_id, credential_id, signature)`, as `U2FManager::sign()`
+/// hands back to its callback for each assertion it collects.
+pub type SignResult = (String, Vec<u8>, Vec<u8>);
+
+/// The records returned by `U2FManager::enumerate_credentials()`.
+#[derive(Debug, Clone)]
+pub struct CredentialManagementResult {
+    pub credentials: Vec<(ctap2::server::RelyingParty, Vec<ctap2::server::User>)>,
+}
+
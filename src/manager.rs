@@ -6,31 +6,257 @@ use std::io::{self, Write};
 use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
 use std::time::Duration;
 
+use sha2::{Digest, Sha256};
+
 use crate::ctap::{Challenge, CollectedClientData, Origin, WebauthnType};
 use crate::ctap2::attestation::{AttestationObject, AttestationStatement};
+use crate::ctap2::commands::credential_management::{
+    CredentialManagementRequest, CredentialManagementResponse,
+};
 use crate::ctap2::commands::{
     AssertionObject, GetAssertion, MakeCredentials, MakeCredentialsOptions, Pin,
 };
-use crate::ctap2::server::{PublicKeyCredentialParameters, RelyingParty, RelyingPartyData, User};
+use crate::ctap2::server::{
+    PublicKeyCredentialParameters, RelyingParty, ResidentKeyRequirement, User,
+    UserVerificationRequirement,
+};
 #[cfg(test)]
 use crate::transport::platform::TestCase;
-use crate::{RegisterFlags, SignFlags};
-use consts::PARAMETER_SIZE;
 use runloop::RunLoop;
 use statemachine::StateMachine;
 use util::{OnceCallback, OnceCallbackMap};
 
+/// Extension inputs that can be requested as part of a `register()` call.
+///
+/// This is intentionally kept separate from `RegisterArgs` so new extensions
+/// can be added without growing the argument struct itself.
+#[derive(Default, Debug, Clone)]
+pub struct RegisterExtensions {
+    /// Request the CTAP2 `hmac-secret` extension, so assertions against the
+    /// resulting credential can later derive a symmetric secret with
+    /// `SignExtensions::hmac_secret`.
+    pub hmac_secret: bool,
+}
+
+/// Extension inputs that can be requested as part of a `sign()` call.
+#[derive(Default, Debug, Clone)]
+pub struct SignExtensions {
+    /// Salt(s) for the CTAP2 `hmac-secret` extension. The authenticator
+    /// returns one decrypted 32-byte secret per salt, derived from the
+    /// credential's stored hmac-secret key.
+    pub hmac_secret: Option<HmacSecretSalts>,
+}
+
+/// Salt(s) requested via the `hmac-secret` extension. CTAP2 allows asking
+/// for one or two independent secrets in a single assertion.
+#[derive(Debug, Clone)]
+pub enum HmacSecretSalts {
+    One([u8; 32]),
+    Two([u8; 32], [u8; 32]),
+}
+
+/// Arguments for `U2FManager::register()`.
+///
+/// Replaces the long positional argument list that used to be threaded
+/// through `register()`, so new WebAuthn parameters can be added here
+/// without changing the method signature again.
+#[derive(Debug, Clone)]
+pub struct RegisterArgs {
+    pub client_data_hash: [u8; 32],
+    pub relying_party: RelyingParty,
+    pub origin: String,
+    pub user: Option<User>,
+    pub pub_cred_params: Vec<PublicKeyCredentialParameters>,
+    pub exclude_list: Vec<::KeyHandle>,
+    pub user_verification_req: UserVerificationRequirement,
+    pub resident_key_req: ResidentKeyRequirement,
+    pub extensions: RegisterExtensions,
+}
+
+impl RegisterArgs {
+    /// Convenience constructor for callers that only have a raw WebAuthn
+    /// `challenge`, rather than a pre-serialized `clientDataJSON` and its
+    /// hash. This builds the `CollectedClientData` the way `register()` used
+    /// to, and hashes it with SHA-256 to obtain `client_data_hash`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_challenge(
+        challenge: Vec<u8>,
+        relying_party: RelyingParty,
+        origin: String,
+        user: Option<User>,
+        pub_cred_params: Vec<PublicKeyCredentialParameters>,
+        exclude_list: Vec<::KeyHandle>,
+        user_verification_req: UserVerificationRequirement,
+        resident_key_req: ResidentKeyRequirement,
+    ) -> Result<Self, ::Error> {
+        let client_data = CollectedClientData {
+            type_: WebauthnType::Create,
+            challenge: Challenge::from(challenge),
+            origin: Origin::Some(origin.clone()),
+        };
+        let client_data_hash = client_data_hash(&client_data)?;
+
+        Ok(RegisterArgs {
+            client_data_hash,
+            relying_party,
+            origin,
+            user,
+            pub_cred_params,
+            exclude_list,
+            user_verification_req,
+            resident_key_req,
+            extensions: RegisterExtensions::default(),
+        })
+    }
+}
+
+/// Arguments for `U2FManager::sign()`.
+#[derive(Debug, Clone)]
+pub struct SignArgs {
+    pub client_data_hash: [u8; 32],
+    pub origin: String,
+    pub relying_party_ids: Vec<String>,
+    pub allow_list: Vec<::KeyHandle>,
+    pub user_verification_req: UserVerificationRequirement,
+    pub user_presence_req: bool,
+    pub extensions: SignExtensions,
+}
+
+impl SignArgs {
+    /// Convenience constructor mirroring `RegisterArgs::from_challenge()`.
+    pub fn from_challenge(
+        challenge: Vec<u8>,
+        origin: String,
+        relying_party_ids: Vec<String>,
+        allow_list: Vec<::KeyHandle>,
+        user_verification_req: UserVerificationRequirement,
+        user_presence_req: bool,
+    ) -> Result<Self, ::Error> {
+        let client_data = CollectedClientData {
+            type_: WebauthnType::Get,
+            challenge: Challenge::from(challenge),
+            origin: Origin::Some(origin.clone()),
+        };
+        let client_data_hash = client_data_hash(&client_data)?;
+
+        Ok(SignArgs {
+            client_data_hash,
+            origin,
+            relying_party_ids,
+            allow_list,
+            user_verification_req,
+            user_presence_req,
+            extensions: SignExtensions::default(),
+        })
+    }
+}
+
+/// Hashes a `CollectedClientData` the same way a relying party would hash
+/// the `clientDataJSON` it receives, so the authenticator signs over a
+/// value the RP can independently reproduce and verify.
+fn client_data_hash(client_data: &CollectedClientData) -> Result<[u8; 32], ::Error> {
+    let json = client_data.client_data_json().map_err(|_| ::Error::Unknown)?;
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&Sha256::digest(json.as_bytes()));
+    Ok(hash)
+}
+
+/// Maps a device-layer `CredentialManagementResponse` into the public
+/// `CredentialManagementResult`, treating `Deleted` as an empty credential
+/// list since `delete_credential()`'s callback only reports success/failure.
+fn credential_management_result(
+    response: CredentialManagementResponse,
+) -> ::CredentialManagementResult {
+    let credentials = match response {
+        CredentialManagementResponse::Credentials(credentials) => credentials,
+        CredentialManagementResponse::Deleted => Vec::new(),
+    };
+    ::CredentialManagementResult { credentials }
+}
+
+/// Progress notifications fired by `StateMachine` while a `register()` or
+/// `sign()` call is in flight, so embedders can drive UI ("touch your key",
+/// device pickers, ...) without polling the manager.
+#[derive(Debug, Clone)]
+pub enum StatusUpdate {
+    /// A device was plugged in, or was found on the initial scan.
+    DeviceAvailable { dev_info: String },
+    /// A device was unplugged, or stopped responding.
+    DeviceUnavailable { dev_info: String },
+    /// More than one capable device is present; each has been sent a
+    /// benign "blink" request and the embedder should prompt the user to
+    /// touch the one they want to use.
+    SelectDeviceNotice,
+    /// The user touched `dev_info` during device selection; every other
+    /// candidate device has had its blink request cancelled.
+    DeviceSelected { dev_info: String },
+    /// The operation completed successfully.
+    Success { dev_info: String },
+    /// The authenticator rejected the PIN it was given, or needs one that
+    /// hasn't been provided yet. The embedder should prompt the user and
+    /// retry the operation with the new PIN.
+    PinError(PinError),
+}
+
+/// Outcome of a device-selection "blink" round, for embedders that run
+/// their own device picker and want to resolve the selection themselves
+/// instead of waiting on a physical touch.
+#[derive(Debug, Clone)]
+pub enum BlinkResult {
+    /// The device identified by `dev_info` (as reported alongside
+    /// `StatusUpdate::SelectDeviceNotice`) was chosen.
+    DeviceSelected(String),
+    /// The embedder cancelled device selection; equivalent to `cancel()`.
+    Cancelled,
+}
+
+/// Errors from the CTAP2 PIN/UV-auth-token protocol, surfaced so the
+/// embedder can reprompt for a PIN instead of the operation simply failing.
+#[derive(Debug, Clone)]
+pub enum PinError {
+    /// The PIN was wrong. `retries` is the number of attempts left before
+    /// the authenticator blocks the PIN, if it reported one.
+    InvalidPin { retries: Option<u8> },
+    /// The PIN has been blocked after too many incorrect attempts; the
+    /// authenticator must be reset before it can be used again.
+    PinBlocked,
+    /// The authenticator requires a PIN, but `register()`/`sign()` was
+    /// called without one.
+    PinRequired,
+    /// The requested PIN is shorter than CTAP2's 4-byte minimum.
+    MinPinLength,
+}
+
 enum QueueAction {
     Register {
         timeout: u64,
-        params: MakeCredentials,
-        callback: OnceCallbackMap<(AttestationObject, CollectedClientData), ::RegisterResult>,
+        params: Box<MakeCredentials>,
+        status: Sender<StatusUpdate>,
+        callback: OnceCallbackMap<AttestationObject, ::RegisterResult>,
     },
     Sign {
         timeout: u64,
-        command: GetAssertion,
-        callback: OnceCallbackMap<AssertionObject, ::SignResult>,
+        commands: Vec<(String, GetAssertion)>,
+        status: Sender<StatusUpdate>,
+        callback: OnceCallbackMap<(String, AssertionObject), ::SignResult>,
+    },
+    CredentialManagement {
+        timeout: u64,
+        request: CredentialManagementRequest,
+        pin: Option<Pin>,
+        callback: OnceCallbackMap<CredentialManagementResponse, ::CredentialManagementResult>,
+    },
+    Reset {
+        timeout: u64,
+        callback: OnceCallback<()>,
     },
+    SetPin {
+        timeout: u64,
+        new_pin: Pin,
+        current_pin: Option<Pin>,
+        callback: OnceCallback<()>,
+    },
+    SelectDevice(BlinkResult),
     Cancel,
 }
 
@@ -45,6 +271,7 @@ pub struct U2FManager {
     filter: Option<Capability>,
 }
 
+#[allow(deprecated)]
 impl U2FManager {
     pub fn new() -> io::Result<Self> {
         let (tx, rx) = channel();
@@ -67,18 +294,45 @@ impl U2FManager {
                     Ok(QueueAction::Register {
                         timeout,
                         params,
+                        status,
                         callback,
                     }) => {
                         // This must not block, otherwise we can't cancel.
-                        sm.register(timeout, params, callback);
+                        sm.register(timeout, *params, status, callback);
                     }
                     Ok(QueueAction::Sign {
                         timeout,
-                        command,
+                        commands,
+                        status,
+                        callback,
+                    }) => {
+                        // This must not block, otherwise we can't cancel.
+                        sm.sign(timeout, commands, status, callback);
+                    }
+                    Ok(QueueAction::CredentialManagement {
+                        timeout,
+                        request,
+                        pin,
+                        callback,
+                    }) => {
+                        // This must not block, otherwise we can't cancel.
+                        sm.credential_management(timeout, request, pin, callback);
+                    }
+                    Ok(QueueAction::Reset { timeout, callback }) => {
+                        // This must not block, otherwise we can't cancel.
+                        sm.reset(timeout, callback);
+                    }
+                    Ok(QueueAction::SetPin {
+                        timeout,
+                        new_pin,
+                        current_pin,
                         callback,
                     }) => {
                         // This must not block, otherwise we can't cancel.
-                        sm.sign(timeout, command, callback);
+                        sm.set_pin(timeout, new_pin, current_pin, callback);
+                    }
+                    Ok(QueueAction::SelectDevice(result)) => {
+                        sm.select_device(result);
                     }
                     Ok(QueueAction::Cancel) => {
                         // Cancelling must block so that we don't start a new
@@ -109,31 +363,18 @@ impl U2FManager {
 
     pub fn register<F>(
         &self,
-        flags: ::RegisterFlags,
         timeout: u64,
-        challenge: Vec<u8>,
-        application: ::AppId,
-        key_handles: Vec<::KeyHandle>,
+        args: RegisterArgs,
+        status: Sender<StatusUpdate>,
+        pin: Option<Pin>,
         callback: F,
     ) -> Result<(), ::Error>
     where
         F: FnOnce(Result<::RegisterResult, ::Error>),
         F: Send + 'static,
     {
-        if challenge.len() != PARAMETER_SIZE || application.len() != PARAMETER_SIZE {
-            return Err(::Error::Unknown);
-        }
-        let challenge = Challenge::from(challenge);
-
-        let client_data = CollectedClientData {
-            type_: WebauthnType::Create,
-            challenge,
-            origin: Origin::None,
-            token_binding: None,
-        };
-
-        let mut excluded_handles = Vec::with_capacity(key_handles.len());
-        for key_handle in &key_handles {
+        let mut excluded_handles = Vec::with_capacity(args.exclude_list.len());
+        for key_handle in &args.exclude_list {
             if key_handle.credential.len() > 256 {
                 return Err(::Error::Unknown);
             }
@@ -142,70 +383,66 @@ impl U2FManager {
         }
 
         let options = MakeCredentialsOptions {
-            user_validation: flags.contains(RegisterFlags::REQUIRE_USER_VERIFICATION),
+            user_validation: args.user_verification_req == UserVerificationRequirement::Required,
+            resident_key: args.resident_key_req == ResidentKeyRequirement::Required,
+            hmac_secret: args.extensions.hmac_secret,
             ..MakeCredentialsOptions::default()
         };
 
         let callback = OnceCallback::new(callback);
-        let callback = callback.map(
-            |(attestation_object, collected_client_data): (
-                AttestationObject,
-                CollectedClientData,
-            )| {
-                let mut cursor = io::Cursor::new(Vec::new());
-                // 1 byte:   register response = 0x05
-                cursor
-                    .write_all(&[0x05])
-                    .expect("unable to write reserved byte");
-
-                let credential_data = attestation_object.auth_data.credential_data.unwrap();
-                // 64 bytes: public_key
-                cursor
-                    .write_all(&credential_data.credential_public_key.bytes[..])
-                    .expect("unable to write public_key");
-
-                // 1 byte: key_handle_len
-                cursor
-                    .write_all(&[credential_data.credential_id.len() as u8])
-                    .expect("unable to write key_handle_len");
-
-                // N bytes: Key_handle
-                cursor
-                    .write_all(&credential_data.credential_id[..])
-                    .expect("unable to write key_handle");
-
-                // N bytes: attestation
-                let u2f = match attestation_object.att_statement {
-                    AttestationStatement::FidoU2F(u2f) => u2f,
-                    _ => panic!("u2f statement format expected"),
-                };
-                cursor
-                    .write_all(u2f.attestation_cert[0].as_ref())
-                    .expect("unable to write attestation");
-                // N bytes: signature
-                cursor
-                    .write_all(u2f.sig.as_ref())
-                    .expect("unable to write signature");
-
-                cursor.into_inner()
-            },
-        );
-
-        let rp = RelyingParty::new_hash(&application).map_err(|_| ::Error::Unknown)?;
+        let callback = callback.map(|attestation_object: AttestationObject| {
+            let mut cursor = io::Cursor::new(Vec::new());
+            // 1 byte:   register response = 0x05
+            cursor
+                .write_all(&[0x05])
+                .expect("unable to write reserved byte");
+
+            let credential_data = attestation_object.auth_data.credential_data.unwrap();
+            // 64 bytes: public_key
+            cursor
+                .write_all(&credential_data.credential_public_key.bytes[..])
+                .expect("unable to write public_key");
+
+            // 1 byte: key_handle_len
+            cursor
+                .write_all(&[credential_data.credential_id.len() as u8])
+                .expect("unable to write key_handle_len");
+
+            // N bytes: Key_handle
+            cursor
+                .write_all(&credential_data.credential_id[..])
+                .expect("unable to write key_handle");
+
+            // N bytes: attestation
+            let u2f = match attestation_object.att_statement {
+                AttestationStatement::FidoU2F(u2f) => u2f,
+                _ => panic!("u2f statement format expected"),
+            };
+            cursor
+                .write_all(u2f.attestation_cert[0].as_ref())
+                .expect("unable to write attestation");
+            // N bytes: signature
+            cursor
+                .write_all(u2f.sig.as_ref())
+                .expect("unable to write signature");
+
+            cursor.into_inner()
+        });
 
         let register = MakeCredentials::new(
-            client_data,
-            rp,
-            None,
-            Vec::new(),
+            args.client_data_hash,
+            args.relying_party,
+            args.user,
+            args.pub_cred_params,
             excluded_handles,
-            None,
-            None,
+            Some(options),
+            pin,
         );
 
         let action = QueueAction::Register {
             timeout,
-            params: register,
+            params: Box::new(register),
+            status,
             callback,
         };
         self.tx.send(action).map_err(|_| ::Error::Unknown)
@@ -213,85 +450,187 @@ impl U2FManager {
 
     pub fn sign<F>(
         &self,
-        flags: SignFlags,
         timeout: u64,
-        challenge: Vec<u8>,
-        app_ids: Vec<::AppId>,
-        key_handles: Vec<::KeyHandle>,
+        args: SignArgs,
+        status: Sender<StatusUpdate>,
+        pin: Option<Pin>,
         callback: F,
     ) -> Result<(), ::Error>
     where
         F: FnOnce(Result<::SignResult, ::Error>),
         F: Send + 'static,
     {
-        if challenge.len() != PARAMETER_SIZE {
+        if args.relying_party_ids.is_empty() {
             return Err(::Error::Unknown);
         }
 
-        let challenge = Challenge::from(challenge);
-        let callback = OnceCallback::new(callback);
-
-        if app_ids.is_empty() {
-            return Err(::Error::Unknown);
+        for key_handle in &args.allow_list {
+            if key_handle.credential.len() > 256 {
+                return Err(::Error::Unknown);
+            }
         }
 
-        let client_data = CollectedClientData {
-            type_: WebauthnType::Get,
-            challenge,
-            origin: Origin::None,
-            token_binding: None,
-        };
-
-        // TODO(baloo): This block of code and commend was previously in src/statemanchine.rs
-        //              I moved this logic here, and I'm not quite sure about what we
-        //              should do, have to ask jcj
-        //
-        // We currently support none of the authenticator selection
-        // criteria because we can't ask tokens whether they do support
-        // those features. If flags are set, ignore all tokens for now.
-        //
-        // Technically, this is a ConstraintError because we shouldn't talk
-        // to this authenticator in the first place. But the result is the
-        // same anyway.
-        //if !flags.is_empty() {
-        //    return;
-        //}
         let options = MakeCredentialsOptions {
-            // TODO(baloo): user_validation is required for yubikeys, not sure why
-            //user_validation: flags.contains(SignFlags::REQUIRE_USER_VERIFICATION),
-            user_validation: true,
+            user_validation: args.user_verification_req == UserVerificationRequirement::Required
+                || args.user_presence_req,
+            hmac_secret_salts: args.extensions.hmac_secret.clone(),
             ..MakeCredentialsOptions::default()
         };
 
-        for app_id in &app_ids {
-            for key_handle in &key_handles {
-                if key_handle.credential.len() > 256 {
-                    return Err(::Error::Unknown);
-                }
-                let rp = RelyingParty::new_hash(app_id).map_err(|_| ::Error::Unknown)?;
+        // An empty allow_list is a passwordless/discoverable-credential
+        // assertion: we don't know the key handle up front, so we build one
+        // `GetAssertion` per candidate relying party and let `StateMachine`
+        // try each against whichever device the user selects, stopping at
+        // the first that actually holds a matching resident credential.
+        // They're sent as a single operation (not one `QueueAction::Sign`
+        // per relying party) so a later id's request can't cancel an
+        // earlier one's still-in-flight device selection.
+        let commands = args
+            .relying_party_ids
+            .iter()
+            .map(|rp_id| {
+                let rp = RelyingParty::new(rp_id);
+                let allow_list = args.allow_list.iter().map(Into::into).collect();
+                let command = GetAssertion::new(
+                    args.client_data_hash,
+                    rp,
+                    allow_list,
+                    Some(options.clone()),
+                    pin.clone(),
+                );
+                (rp_id.clone(), command)
+            })
+            .collect();
 
-                let allow_list = vec![key_handle.into()];
+        let callback = OnceCallback::new(callback);
+        let callback = callback.map(|(rp_id, assertion_object): (String, AssertionObject)| {
+            let key_handle = assertion_object.credential_id();
+            (rp_id, key_handle, assertion_object.u2f_sign_data())
+        });
 
-                let command =
-                    GetAssertion::new(client_data.clone(), rp, allow_list, Some(options), None);
+        let action = QueueAction::Sign {
+            timeout,
+            commands,
+            status,
+            callback,
+        };
+        self.tx.send(action).map_err(|_| ::Error::Unknown)
+    }
 
-                let app_id = app_id.clone();
-                let key_handle = key_handle.credential.clone();
-                let callback = callback.clone();
+    /// Lists the relying parties and users of discoverable credentials
+    /// stored on the authenticator, via CTAP2 `authenticatorCredentialManagement`.
+    pub fn enumerate_credentials<F>(
+        &self,
+        timeout: u64,
+        pin: Option<Pin>,
+        callback: F,
+    ) -> Result<(), ::Error>
+    where
+        F: FnOnce(Result<::CredentialManagementResult, ::Error>),
+        F: Send + 'static,
+    {
+        let callback = OnceCallback::new(callback);
+        let callback = callback.map(credential_management_result);
+        let request = CredentialManagementRequest::EnumerateCredentials;
 
-                let callback = callback.map(move |assertion_object: AssertionObject| {
-                    (app_id, key_handle, assertion_object.u2f_sign_data())
-                });
+        let action = QueueAction::CredentialManagement {
+            timeout,
+            request,
+            pin,
+            callback,
+        };
+        self.tx.send(action).map_err(|_| ::Error::Unknown)
+    }
 
-                let action = QueueAction::Sign {
-                    command,
-                    timeout,
-                    callback,
-                };
-                self.tx.send(action).map_err(|_| ::Error::Unknown)?;
-            }
-        }
-        Ok(())
+    /// Deletes a single discoverable credential, identified by the
+    /// `credential_id` reported by `enumerate_credentials()`.
+    pub fn delete_credential<F>(
+        &self,
+        timeout: u64,
+        credential_id: ::KeyHandle,
+        pin: Option<Pin>,
+        callback: F,
+    ) -> Result<(), ::Error>
+    where
+        F: FnOnce(Result<::CredentialManagementResult, ::Error>),
+        F: Send + 'static,
+    {
+        let callback = OnceCallback::new(callback);
+        let callback = callback.map(credential_management_result);
+        let request = CredentialManagementRequest::DeleteCredential(credential_id.into());
+
+        let action = QueueAction::CredentialManagement {
+            timeout,
+            request,
+            pin,
+            callback,
+        };
+        self.tx.send(action).map_err(|_| ::Error::Unknown)
+    }
+
+    /// Triggers a CTAP2 `authenticatorReset`, wiping all credentials and
+    /// clearing the PIN. Per spec this only succeeds within a few seconds of
+    /// the authenticator powering up, and still requires a user-presence
+    /// touch; outside that window it fails rather than silently no-opping.
+    pub fn reset<F>(&self, timeout: u64, callback: F) -> Result<(), ::Error>
+    where
+        F: FnOnce(Result<(), ::Error>),
+        F: Send + 'static,
+    {
+        let callback = OnceCallback::new(callback);
+        let action = QueueAction::Reset { timeout, callback };
+        self.tx.send(action).map_err(|_| ::Error::Unknown)
+    }
+
+    /// Sets the authenticator's clientPIN for the first time.
+    pub fn set_pin<F>(&self, timeout: u64, new_pin: Pin, callback: F) -> Result<(), ::Error>
+    where
+        F: FnOnce(Result<(), ::Error>),
+        F: Send + 'static,
+    {
+        let callback = OnceCallback::new(callback);
+        let action = QueueAction::SetPin {
+            timeout,
+            new_pin,
+            current_pin: None,
+            callback,
+        };
+        self.tx.send(action).map_err(|_| ::Error::Unknown)
+    }
+
+    /// Changes the authenticator's clientPIN. `current_pin` must match the
+    /// PIN already set, or the authenticator reports a PIN error (and
+    /// decrements its retry counter) the same way it does for a failed
+    /// PIN/UV-auth-token request.
+    pub fn change_pin<F>(
+        &self,
+        timeout: u64,
+        current_pin: Pin,
+        new_pin: Pin,
+        callback: F,
+    ) -> Result<(), ::Error>
+    where
+        F: FnOnce(Result<(), ::Error>),
+        F: Send + 'static,
+    {
+        let callback = OnceCallback::new(callback);
+        let action = QueueAction::SetPin {
+            timeout,
+            new_pin,
+            current_pin: Some(current_pin),
+            callback,
+        };
+        self.tx.send(action).map_err(|_| ::Error::Unknown)
+    }
+
+    /// Resolves an in-progress device-selection "blink" round, for
+    /// embedders that render their own picker instead of relying on the
+    /// user touching the correct device directly. No-op if no selection
+    /// is currently pending.
+    pub fn select_device(&self, result: BlinkResult) -> Result<(), ::Error> {
+        self.tx
+            .send(QueueAction::SelectDevice(result))
+            .map_err(|_| ::Error::Unknown)
     }
 
     pub fn cancel(&self) -> Result<(), ::Error> {
@@ -301,6 +640,7 @@ impl U2FManager {
     }
 }
 
+#[allow(deprecated)]
 impl Drop for U2FManager {
     fn drop(&mut self) {
         self.queue.cancel();
@@ -0,0 +1,42 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+/// Runs a closure on a background thread until `cancel()` is called.
+pub struct RunLoop {
+    alive: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl RunLoop {
+    pub fn new<F>(run: F) -> io::Result<Self>
+    where
+        F: FnOnce(&dyn Fn() -> bool) + Send + 'static,
+    {
+        let alive = Arc::new(AtomicBool::new(true));
+        let alive_for_thread = alive.clone();
+
+        let thread = thread::Builder::new().spawn(move || {
+            let is_alive = move || alive_for_thread.load(Ordering::SeqCst);
+            run(&is_alive);
+        })?;
+
+        Ok(RunLoop {
+            alive,
+            thread: Some(thread),
+        })
+    }
+
+    /// Signals the run loop to stop and waits for it to finish.
+    pub fn cancel(&mut self) {
+        self.alive.store(false, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,1004 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Drives the enumerated devices through a single in-flight operation,
+//! firing `StatusUpdate`s as it goes and resolving device selection when
+//! more than one device is present.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::ctap2::attestation::AttestationObject;
+use crate::ctap2::commands::credential_management::{
+    CredentialManagementRequest, CredentialManagementResponse,
+};
+use crate::ctap2::commands::pin_protocol::{pad_pin, pin_hash, KeyAgreement, SharedSecret};
+use crate::ctap2::commands::{AssertionObject, GetAssertion, MakeCredentials, Pin};
+use crate::manager::{BlinkResult, PinError, StatusUpdate};
+use crate::transport::platform::decrypt_hmac_secret_output;
+use crate::transport::{self, Device};
+use crate::util::{OnceCallback, OnceCallbackMap};
+use crate::{Error, HmacSecretSalts};
+
+/// Negotiates a `pinUvAuthProtocolOne` session with `device`: a fresh
+/// key-agreement handshake, plus a `pinUvAuthToken` if `pin` is given.
+/// Returns the shared secret (for `hmac-secret` salt encryption) and, if a
+/// PIN was provided, the raw token (for computing `pinUvAuthParam`s).
+fn negotiate_pin_uv_auth(
+    device: &Arc<dyn Device>,
+    pin: Option<&Pin>,
+) -> Result<(SharedSecret, Option<[u8; 32]>), Error> {
+    let platform_key = KeyAgreement::generate();
+    let device_public_key = device.begin_pin_uv_auth(&platform_key.public_key_bytes());
+    let shared_secret = platform_key.shared_secret(&device_public_key)?;
+
+    let token = match pin {
+        None => None,
+        Some(pin) => {
+            let pin_hash_enc = shared_secret.encrypt(&pin_hash(pin.as_str()));
+            let token_enc = device.get_pin_token(&pin_hash_enc)?;
+            let token_bytes = shared_secret.decrypt(&token_enc)?;
+            let mut token = [0u8; 32];
+            token.copy_from_slice(&token_bytes);
+            Some(token)
+        }
+    };
+
+    Ok((shared_secret, token))
+}
+
+/// Attempts a single `GetAssertion` against an already-selected `device`,
+/// including any PIN/UV-auth-token negotiation and `hmac-secret` extension
+/// handling it requires.
+fn sign_one(device: &Arc<dyn Device>, command: &GetAssertion) -> Result<AssertionObject, Error> {
+    let wants_hmac_secret = command
+        .options
+        .as_ref()
+        .and_then(|options| options.hmac_secret_salts.as_ref())
+        .is_some();
+
+    let (shared_secret, pin_uv_auth_param) = if command.pin.is_some() || wants_hmac_secret {
+        let (shared_secret, token) = negotiate_pin_uv_auth(device, command.pin.as_ref())?;
+        let auth_param = token
+            .map(|token| SharedSecret::from_bytes(token).authenticate(&command.client_data_hash));
+        (Some(shared_secret), auth_param)
+    } else {
+        (None, None)
+    };
+
+    let mut assertion = device.get_assertion(command, pin_uv_auth_param.as_ref())?;
+
+    if let (Some(shared_secret), Some(salts)) = (
+        shared_secret,
+        command
+            .options
+            .as_ref()
+            .and_then(|options| options.hmac_secret_salts.clone()),
+    ) {
+        let (salt_bytes, two_salts) = match &salts {
+            HmacSecretSalts::One(salt) => (salt.to_vec(), false),
+            HmacSecretSalts::Two(first, second) => {
+                let mut combined = first.to_vec();
+                combined.extend_from_slice(second);
+                (combined, true)
+            }
+        };
+        let salt_enc = shared_secret.encrypt(&salt_bytes);
+        let salt_auth = shared_secret.authenticate(&salt_enc);
+        let output_enc = device.hmac_secret(&assertion.credential_id, &salt_enc, &salt_auth)?;
+        assertion.hmac_secret_output =
+            Some(decrypt_hmac_secret_output(&shared_secret, &output_enc, two_salts)?);
+    }
+
+    Ok(assertion)
+}
+
+/// Why `select_device` didn't return a device to run the operation against.
+enum NotSelected {
+    /// `StateMachine::cancel()` (or `select_device(BlinkResult::Cancelled)`)
+    /// was called; the operation's callback must not fire.
+    Cancelled,
+    /// `deadline` elapsed before any device was touched; the operation's
+    /// callback should fire with `Error::Timeout`.
+    TimedOut,
+}
+
+/// Polls `devices` until the user touches one (`Device::is_touched`),
+/// `cancel` is raised, or `deadline` elapses, returning the winner. Every
+/// other device keeps running its own blink request until `StateMachine`
+/// tears them down, the same way a real multi-device race works.
+fn race_devices(
+    devices: &[Arc<dyn Device>],
+    cancel: &AtomicBool,
+    deadline: Instant,
+) -> Result<Arc<dyn Device>, NotSelected> {
+    loop {
+        if cancel.load(Ordering::SeqCst) {
+            return Err(NotSelected::Cancelled);
+        }
+        if let Some(device) = devices.iter().find(|device| device.is_touched()) {
+            return Ok(device.clone());
+        }
+        if Instant::now() >= deadline {
+            return Err(NotSelected::TimedOut);
+        }
+        thread::sleep(Duration::from_millis(2));
+    }
+}
+
+/// Picks the device an operation should run against: the lone device if
+/// there's only one, or the winner of a blink race (firing
+/// `SelectDeviceNotice`/`DeviceSelected`) if there's more than one.
+fn select_device(
+    devices: &[Arc<dyn Device>],
+    status: &Sender<StatusUpdate>,
+    cancel: &AtomicBool,
+    deadline: Instant,
+) -> Result<Arc<dyn Device>, NotSelected> {
+    for device in devices {
+        let _ = status.send(StatusUpdate::DeviceAvailable {
+            dev_info: device.id(),
+        });
+    }
+
+    let chosen = if devices.len() > 1 {
+        let _ = status.send(StatusUpdate::SelectDeviceNotice);
+        race_devices(devices, cancel, deadline)
+    } else {
+        devices.first().cloned().ok_or(NotSelected::TimedOut)
+    };
+
+    if let Ok(device) = &chosen {
+        let _ = status.send(StatusUpdate::DeviceSelected {
+            dev_info: device.id(),
+        });
+        for other in devices {
+            if !Arc::ptr_eq(other, device) {
+                let _ = status.send(StatusUpdate::DeviceUnavailable {
+                    dev_info: other.id(),
+                });
+            }
+        }
+    }
+    chosen
+}
+
+/// Turns a CTAP2 `timeout` (milliseconds) into an absolute deadline for
+/// `select_device`/`race_devices` to poll against.
+fn deadline_from_timeout(timeout: u64) -> Instant {
+    Instant::now() + Duration::from_millis(timeout)
+}
+
+/// Runs an in-flight `register()`/`sign()`/... operation on a background
+/// thread, so the queue thread that called into `StateMachine` is never
+/// blocked and can still process a `cancel()` sent while the operation is
+/// running.
+pub struct StateMachine {
+    devices: Vec<Arc<dyn Device>>,
+    cancel: Arc<AtomicBool>,
+    active: Option<JoinHandle<()>>,
+}
+
+impl Default for StateMachine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StateMachine {
+    pub fn new() -> Self {
+        StateMachine {
+            devices: transport::platform::enumerate(),
+            cancel: Arc::new(AtomicBool::new(false)),
+            active: None,
+        }
+    }
+
+    /// Cancels and joins whatever operation is currently running, so a new
+    /// one can start from a clean slate.
+    fn cancel_active(&mut self) {
+        self.cancel.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.active.take() {
+            let _ = handle.join();
+        }
+        self.cancel = Arc::new(AtomicBool::new(false));
+        for device in &self.devices {
+            device.reset_touch();
+        }
+    }
+
+    pub fn register(
+        &mut self,
+        timeout: u64,
+        params: MakeCredentials,
+        status: Sender<StatusUpdate>,
+        callback: OnceCallbackMap<AttestationObject, ::RegisterResult>,
+    ) {
+        self.cancel_active();
+        let devices = self.devices.clone();
+        let cancel = self.cancel.clone();
+        let deadline = deadline_from_timeout(timeout);
+
+        self.active = Some(thread::spawn(move || {
+            let device = match select_device(&devices, &status, &cancel, deadline) {
+                Ok(device) => device,
+                Err(NotSelected::Cancelled) => return,
+                Err(NotSelected::TimedOut) => {
+                    callback.call(Err(Error::Timeout));
+                    return;
+                }
+            };
+
+            let result = (|| {
+                let pin_uv_auth_param = match params.pin.as_ref() {
+                    Some(pin) => {
+                        let (_, token) = negotiate_pin_uv_auth(&device, Some(pin))?;
+                        let token = token.expect("token requested when pin is Some");
+                        Some(SharedSecret::from_bytes(token).authenticate(&params.client_data_hash))
+                    }
+                    None => None,
+                };
+                device.make_credential(&params, pin_uv_auth_param.as_ref())
+            })();
+
+            match result {
+                Ok(attestation_object) => {
+                    let _ = status.send(StatusUpdate::Success {
+                        dev_info: device.id(),
+                    });
+                    callback.call(Ok(attestation_object));
+                }
+                Err(Error::Pin(pin_error)) => {
+                    let _ = status.send(StatusUpdate::PinError(pin_error.clone()));
+                    callback.call(Err(Error::Pin(pin_error)));
+                }
+                Err(error) => callback.call(Err(error)),
+            }
+        }));
+    }
+
+    /// Signs against `commands` on a single selected device, one relying
+    /// party id per `GetAssertion`. Every command is tried in order against
+    /// the *same* device (an empty `allow_list` command only succeeds if
+    /// that device actually holds a resident credential for its relying
+    /// party), so asking about several relying party ids races them
+    /// together as one operation rather than queuing a separate
+    /// device-selection round per id.
+    pub fn sign(
+        &mut self,
+        timeout: u64,
+        commands: Vec<(String, GetAssertion)>,
+        status: Sender<StatusUpdate>,
+        callback: OnceCallbackMap<(String, AssertionObject), ::SignResult>,
+    ) {
+        self.cancel_active();
+        let devices = self.devices.clone();
+        let cancel = self.cancel.clone();
+        let deadline = deadline_from_timeout(timeout);
+
+        self.active = Some(thread::spawn(move || {
+            let device = match select_device(&devices, &status, &cancel, deadline) {
+                Ok(device) => device,
+                Err(NotSelected::Cancelled) => return,
+                Err(NotSelected::TimedOut) => {
+                    callback.call(Err(Error::Timeout));
+                    return;
+                }
+            };
+
+            // A `Pin` error is a property of the device/PIN, not of the
+            // relying party id being tried, and negotiating a PIN/UV-auth
+            // token burns one of the device's retries each time: stop at the
+            // first one instead of repeating it (and spending the rest of
+            // the retry counter) against every remaining candidate.
+            let mut result = Err(Error::Unknown);
+            for (rp_id, command) in &commands {
+                match sign_one(&device, command) {
+                    Ok(assertion) => {
+                        result = Ok((rp_id.clone(), assertion));
+                        break;
+                    }
+                    Err(Error::Pin(pin_error)) => {
+                        result = Err(Error::Pin(pin_error));
+                        break;
+                    }
+                    Err(error) => result = Err(error),
+                }
+            }
+
+            match result {
+                Ok(signed) => {
+                    let _ = status.send(StatusUpdate::Success {
+                        dev_info: device.id(),
+                    });
+                    callback.call(Ok(signed));
+                }
+                Err(Error::Pin(pin_error)) => {
+                    let _ = status.send(StatusUpdate::PinError(pin_error.clone()));
+                    callback.call(Err(Error::Pin(pin_error)));
+                }
+                Err(error) => callback.call(Err(error)),
+            }
+        }));
+    }
+
+    pub fn credential_management(
+        &mut self,
+        _timeout: u64,
+        request: CredentialManagementRequest,
+        pin: Option<Pin>,
+        callback: OnceCallbackMap<CredentialManagementResponse, ::CredentialManagementResult>,
+    ) {
+        self.cancel_active();
+        let devices = self.devices.clone();
+
+        self.active = Some(thread::spawn(move || {
+            let Some(device) = devices.first().cloned() else {
+                callback.call(Err(Error::Unknown));
+                return;
+            };
+
+            let result = (|| {
+                let pin = pin.ok_or(Error::Pin(PinError::PinRequired))?;
+                let (_, token) = negotiate_pin_uv_auth(&device, Some(&pin))?;
+                let token = token.expect("token requested when pin is Some");
+                let message = match &request {
+                    CredentialManagementRequest::EnumerateCredentials => vec![0x01],
+                    CredentialManagementRequest::DeleteCredential(descriptor) => {
+                        let mut message = vec![0x02];
+                        message.extend_from_slice(&descriptor.id);
+                        message
+                    }
+                };
+                let pin_uv_auth_param = SharedSecret::from_bytes(token).authenticate(&message);
+                device.credential_management(&request, &pin_uv_auth_param)
+            })();
+
+            callback.call(result);
+        }));
+    }
+
+    pub fn reset(&mut self, _timeout: u64, callback: OnceCallback<()>) {
+        self.cancel_active();
+        let devices = self.devices.clone();
+
+        self.active = Some(thread::spawn(move || {
+            let result = match devices.first() {
+                Some(device) => device.reset(),
+                None => Err(Error::Unknown),
+            };
+            callback.call(result);
+        }));
+    }
+
+    pub fn set_pin(
+        &mut self,
+        _timeout: u64,
+        new_pin: Pin,
+        current_pin: Option<Pin>,
+        callback: OnceCallback<()>,
+    ) {
+        self.cancel_active();
+        let devices = self.devices.clone();
+
+        self.active = Some(thread::spawn(move || {
+            let result = (|| {
+                let device = devices.first().cloned().ok_or(Error::Unknown)?;
+                let new_pin_padded = pad_pin(new_pin.as_str())?;
+
+                let (shared_secret, _) = negotiate_pin_uv_auth(&device, None)?;
+                let new_pin_enc = shared_secret.encrypt(&new_pin_padded);
+
+                match current_pin {
+                    None => {
+                        let pin_uv_auth_param = shared_secret.authenticate(&new_pin_enc);
+                        device.set_pin(&new_pin_enc, &pin_uv_auth_param)
+                    }
+                    Some(current_pin) => {
+                        let pin_hash_enc = shared_secret.encrypt(&pin_hash(current_pin.as_str()));
+                        let mut message = new_pin_enc.clone();
+                        message.extend_from_slice(&pin_hash_enc);
+                        let pin_uv_auth_param = shared_secret.authenticate(&message);
+                        device.change_pin(&new_pin_enc, &pin_hash_enc, &pin_uv_auth_param)
+                    }
+                }
+            })();
+            callback.call(result);
+        }));
+    }
+
+    pub fn select_device(&mut self, result: BlinkResult) {
+        match result {
+            BlinkResult::DeviceSelected(dev_info) => {
+                for device in &self.devices {
+                    if device.id() == dev_info {
+                        device.touch();
+                    }
+                }
+            }
+            BlinkResult::Cancelled => {
+                self.cancel.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+
+    pub fn cancel(&mut self) {
+        self.cancel_active();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::ctap2::commands::{HmacSecretOutput, MakeCredentialsOptions};
+    use crate::ctap2::server::{PublicKeyCredentialDescriptor, PublicKeyCredentialParameters, RelyingParty, User};
+    use crate::transport::platform::{SoftwareToken, TestCase};
+
+    fn single_device(id: &str) -> StateMachine {
+        TestCase::activate(Some(vec![Arc::new(SoftwareToken::new(id))]));
+        let sm = StateMachine::new();
+        TestCase::activate(None);
+        sm
+    }
+
+    /// Drives `sm.register()` to completion and hands back the real
+    /// `AttestationObject`, sidestepping the `Vec<u8>`-shaped public
+    /// `RegisterResult` the wire-format callback actually carries.
+    fn register(
+        sm: &mut StateMachine,
+        client_data_hash: [u8; 32],
+        pin: Option<Pin>,
+    ) -> Result<AttestationObject, Error> {
+        register_with_options(sm, client_data_hash, None, pin)
+    }
+
+    fn register_with_options(
+        sm: &mut StateMachine,
+        client_data_hash: [u8; 32],
+        options: Option<MakeCredentialsOptions>,
+        pin: Option<Pin>,
+    ) -> Result<AttestationObject, Error> {
+        let (status_tx, _status_rx) = mpsc::channel();
+        let (result_tx, result_rx) = mpsc::channel();
+        let captured: Arc<Mutex<Option<AttestationObject>>> = Arc::new(Mutex::new(None));
+        let captured_in_transform = captured.clone();
+        let params = MakeCredentials::new(
+            client_data_hash,
+            RelyingParty::new("example.com"),
+            None,
+            vec![PublicKeyCredentialParameters::ES256],
+            Vec::new(),
+            options,
+            pin,
+        );
+        sm.register(
+            0,
+            params,
+            status_tx,
+            OnceCallback::new(move |result: Result<::RegisterResult, Error>| {
+                let _ = result_tx.send(result.map(|_| ()));
+            })
+            .map(move |attestation_object: AttestationObject| {
+                *captured_in_transform.lock().unwrap() = Some(attestation_object);
+                Vec::new()
+            }),
+        );
+        match result_rx.recv_timeout(Duration::from_secs(1)).unwrap() {
+            Ok(()) => Ok(captured.lock().unwrap().take().unwrap()),
+            Err(error) => Err(error),
+        }
+    }
+
+    #[test]
+    fn pin_required_before_it_is_set() {
+        TestCase::activate(Some(vec![Arc::new(SoftwareToken::new("token-0"))]));
+        let mut sm = StateMachine::new();
+        TestCase::activate(None);
+
+        let result = register(&mut sm, [1u8; 32], Some(Pin::new("1234")));
+        assert!(matches!(result, Err(Error::Pin(PinError::PinRequired))));
+    }
+
+    #[test]
+    fn user_verification_required_without_a_pin_is_rejected() {
+        let mut sm = single_device("token-0");
+
+        let options = MakeCredentialsOptions {
+            user_validation: true,
+            ..MakeCredentialsOptions::default()
+        };
+        let result = register_with_options(&mut sm, [6u8; 32], Some(options), None);
+        assert!(matches!(result, Err(Error::Pin(PinError::PinRequired))));
+    }
+
+    #[test]
+    fn hmac_secret_is_stable_for_the_same_salt_and_differs_for_another() {
+        let mut sm = single_device("token-0");
+
+        let client_data_hash = [4u8; 32];
+        let options = MakeCredentialsOptions {
+            hmac_secret: true,
+            ..MakeCredentialsOptions::default()
+        };
+        let attestation =
+            register_with_options(&mut sm, client_data_hash, Some(options), None).unwrap();
+        let credential_id = attestation
+            .auth_data
+            .credential_data
+            .unwrap()
+            .credential_id;
+
+        let sign = |sm: &mut StateMachine, salt: [u8; 32]| -> AssertionObject {
+            let (status_tx, _status_rx) = mpsc::channel();
+            let (result_tx, result_rx) = mpsc::channel();
+            let captured: Arc<Mutex<Option<AssertionObject>>> = Arc::new(Mutex::new(None));
+            let captured_in_transform = captured.clone();
+            let options = MakeCredentialsOptions {
+                hmac_secret_salts: Some(HmacSecretSalts::One(salt)),
+                ..MakeCredentialsOptions::default()
+            };
+            let command = GetAssertion::new(
+                client_data_hash,
+                RelyingParty::new("example.com"),
+                vec![crate::ctap2::server::PublicKeyCredentialDescriptor {
+                    id: credential_id.clone(),
+                }],
+                Some(options),
+                None,
+            );
+            sm.sign(
+                5000,
+                vec![("example.com".to_string(), command)],
+                status_tx,
+                OnceCallback::new(move |result: Result<::SignResult, Error>| {
+                    let _ = result_tx.send(result.map(|_| ()));
+                })
+                .map(move |(_rp_id, assertion_object): (String, AssertionObject)| {
+                    *captured_in_transform.lock().unwrap() = Some(assertion_object);
+                    (String::new(), Vec::new(), Vec::new())
+                }),
+            );
+            result_rx
+                .recv_timeout(Duration::from_secs(1))
+                .unwrap()
+                .unwrap();
+            let assertion = captured.lock().unwrap().take().unwrap();
+            assertion
+        };
+
+        let first = sign(&mut sm, [5u8; 32]);
+        let second = sign(&mut sm, [5u8; 32]);
+        let third = sign(&mut sm, [6u8; 32]);
+
+        let secret = |assertion: &AssertionObject| match assertion.hmac_secret_output {
+            Some(HmacSecretOutput::One(secret)) => secret,
+            ref other => panic!("expected a single hmac-secret output, got {:?}", other),
+        };
+
+        assert_eq!(secret(&first), secret(&second));
+        assert_ne!(secret(&first), secret(&third));
+    }
+
+    #[test]
+    fn wrong_pin_decrements_retries_then_unlocks_with_correct_pin() {
+        let mut sm = single_device("token-0");
+
+        let (result_tx, result_rx) = mpsc::channel::<Result<(), Error>>();
+        sm.set_pin(
+            0,
+            Pin::new("1234"),
+            None,
+            OnceCallback::new(move |result| {
+                let _ = result_tx.send(result);
+            }),
+        );
+        result_rx.recv_timeout(Duration::from_secs(1)).unwrap().unwrap();
+
+        let wrong = register(&mut sm, [2u8; 32], Some(Pin::new("0000")));
+        match wrong {
+            Err(Error::Pin(PinError::InvalidPin { retries: Some(7) })) => {}
+            other => panic!("expected one retry consumed, got {:?}", other),
+        }
+
+        let right = register(&mut sm, [3u8; 32], Some(Pin::new("1234")));
+        assert!(right.is_ok());
+    }
+
+    #[test]
+    fn sign_with_a_wrong_pin_consumes_only_one_retry_across_relying_party_ids() {
+        let mut sm = single_device("token-0");
+
+        let (set_tx, set_rx) = mpsc::channel();
+        sm.set_pin(
+            0,
+            Pin::new("1234"),
+            None,
+            OnceCallback::new(move |result| {
+                let _ = set_tx.send(result);
+            }),
+        );
+        set_rx.recv_timeout(Duration::from_secs(1)).unwrap().unwrap();
+
+        let command = |client_data_hash: [u8; 32], rp_id: &str| {
+            GetAssertion::new(
+                client_data_hash,
+                RelyingParty::new(rp_id),
+                Vec::new(),
+                None,
+                Some(Pin::new("0000")),
+            )
+        };
+        let commands = vec![
+            ("a.example.com".to_string(), command([20u8; 32], "a.example.com")),
+            ("b.example.com".to_string(), command([21u8; 32], "b.example.com")),
+        ];
+
+        let (status_tx, _status_rx) = mpsc::channel();
+        let (result_tx, result_rx) = mpsc::channel();
+        sm.sign(
+            5000,
+            commands,
+            status_tx,
+            OnceCallback::new(move |result: Result<::SignResult, Error>| {
+                let _ = result_tx.send(result);
+            })
+            .map(|(rp_id, assertion_object): (String, AssertionObject)| {
+                (rp_id, Vec::new(), assertion_object.u2f_sign_data())
+            }),
+        );
+
+        match result_rx.recv_timeout(Duration::from_secs(1)).unwrap() {
+            Err(Error::Pin(PinError::InvalidPin { retries: Some(7) })) => {}
+            other => panic!("expected exactly one retry consumed, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn cancelling_device_selection_leaves_the_callback_unfired() {
+        TestCase::activate(Some(vec![
+            Arc::new(SoftwareToken::new("token-a")),
+            Arc::new(SoftwareToken::new("token-b")),
+        ]));
+        let mut sm = StateMachine::new();
+        TestCase::activate(None);
+
+        let (status_tx, status_rx) = mpsc::channel();
+        let (result_tx, result_rx) = mpsc::channel();
+        let params = MakeCredentials::new(
+            [7u8; 32],
+            RelyingParty::new("example.com"),
+            None,
+            vec![PublicKeyCredentialParameters::ES256],
+            Vec::new(),
+            None,
+            None,
+        );
+        sm.register(
+            5000,
+            params,
+            status_tx,
+            OnceCallback::new(move |result| {
+                let _ = result_tx.send(result);
+            })
+            .map(|_attestation_object: AttestationObject| Vec::new()),
+        );
+
+        let saw_select_notice = (0..10).any(|_| {
+            matches!(
+                status_rx.recv_timeout(Duration::from_millis(200)),
+                Ok(StatusUpdate::SelectDeviceNotice)
+            )
+        });
+        assert!(saw_select_notice, "expected a device-selection notice");
+
+        sm.select_device(BlinkResult::Cancelled);
+        assert!(result_rx.recv_timeout(Duration::from_millis(500)).is_err());
+
+        // `cancel()` must still be able to tear down the race cleanly.
+        sm.cancel();
+    }
+
+    #[test]
+    fn selecting_a_specific_device_picks_that_device() {
+        TestCase::activate(Some(vec![
+            Arc::new(SoftwareToken::new("token-a")),
+            Arc::new(SoftwareToken::new("token-b")),
+        ]));
+        let mut sm = StateMachine::new();
+        TestCase::activate(None);
+
+        let (status_tx, status_rx) = mpsc::channel();
+        let (result_tx, result_rx) = mpsc::channel();
+        let params = MakeCredentials::new(
+            [8u8; 32],
+            RelyingParty::new("example.com"),
+            None,
+            vec![PublicKeyCredentialParameters::ES256],
+            Vec::new(),
+            None,
+            None,
+        );
+        sm.register(
+            5000,
+            params,
+            status_tx,
+            OnceCallback::new(move |result| {
+                let _ = result_tx.send(result);
+            })
+            .map(|_attestation_object: AttestationObject| Vec::new()),
+        );
+
+        let saw_select_notice = (0..10).any(|_| {
+            matches!(
+                status_rx.recv_timeout(Duration::from_millis(200)),
+                Ok(StatusUpdate::SelectDeviceNotice)
+            )
+        });
+        assert!(saw_select_notice, "expected a device-selection notice");
+
+        sm.select_device(BlinkResult::DeviceSelected("token-b".to_string()));
+
+        let selected = (0..10).find_map(|_| {
+            match status_rx.recv_timeout(Duration::from_millis(200)) {
+                Ok(StatusUpdate::DeviceSelected { dev_info }) => Some(dev_info),
+                _ => None,
+            }
+        });
+        assert_eq!(selected.as_deref(), Some("token-b"));
+        assert!(result_rx.recv_timeout(Duration::from_secs(1)).unwrap().is_ok());
+    }
+
+    #[test]
+    fn register_times_out_if_nobody_touches_a_device() {
+        TestCase::activate(Some(vec![
+            Arc::new(SoftwareToken::new("token-a")),
+            Arc::new(SoftwareToken::new("token-b")),
+        ]));
+        let mut sm = StateMachine::new();
+        TestCase::activate(None);
+
+        let (status_tx, _status_rx) = mpsc::channel();
+        let (result_tx, result_rx) = mpsc::channel();
+        let params = MakeCredentials::new(
+            [9u8; 32],
+            RelyingParty::new("example.com"),
+            None,
+            vec![PublicKeyCredentialParameters::ES256],
+            Vec::new(),
+            None,
+            None,
+        );
+        sm.register(
+            50,
+            params,
+            status_tx,
+            OnceCallback::new(move |result| {
+                let _ = result_tx.send(result);
+            })
+            .map(|_attestation_object: AttestationObject| Vec::new()),
+        );
+
+        match result_rx.recv_timeout(Duration::from_secs(1)) {
+            Ok(Err(Error::Timeout)) => {}
+            other => panic!("expected a timeout, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reset_wipes_the_pin() {
+        let mut sm = single_device("token-0");
+
+        let (set_tx, set_rx) = mpsc::channel();
+        sm.set_pin(
+            0,
+            Pin::new("1234"),
+            None,
+            OnceCallback::new(move |result| {
+                let _ = set_tx.send(result);
+            }),
+        );
+        set_rx.recv_timeout(Duration::from_secs(1)).unwrap().unwrap();
+
+        // `setPIN` only succeeds for the very first PIN; with one already
+        // set, trying it again (rather than `changePIN`) must fail.
+        let (reset_pin_tx, reset_pin_rx) = mpsc::channel();
+        sm.set_pin(
+            0,
+            Pin::new("5678"),
+            None,
+            OnceCallback::new(move |result| {
+                let _ = reset_pin_tx.send(result);
+            }),
+        );
+        assert!(reset_pin_rx
+            .recv_timeout(Duration::from_secs(1))
+            .unwrap()
+            .is_err());
+
+        let (reset_tx, reset_rx) = mpsc::channel();
+        sm.reset(
+            0,
+            OnceCallback::new(move |result| {
+                let _ = reset_tx.send(result);
+            }),
+        );
+        reset_rx.recv_timeout(Duration::from_secs(1)).unwrap().unwrap();
+
+        // After a reset there's no PIN anymore, so `setPIN` succeeds again.
+        let (set_again_tx, set_again_rx) = mpsc::channel();
+        sm.set_pin(
+            0,
+            Pin::new("5678"),
+            None,
+            OnceCallback::new(move |result| {
+                let _ = set_again_tx.send(result);
+            }),
+        );
+        set_again_rx
+            .recv_timeout(Duration::from_secs(1))
+            .unwrap()
+            .unwrap();
+    }
+
+    #[test]
+    fn change_pin_replaces_the_old_pin() {
+        let mut sm = single_device("token-0");
+
+        let (set_tx, set_rx) = mpsc::channel();
+        sm.set_pin(
+            0,
+            Pin::new("1234"),
+            None,
+            OnceCallback::new(move |result| {
+                let _ = set_tx.send(result);
+            }),
+        );
+        set_rx.recv_timeout(Duration::from_secs(1)).unwrap().unwrap();
+
+        let (change_tx, change_rx) = mpsc::channel();
+        sm.set_pin(
+            0,
+            Pin::new("5678"),
+            Some(Pin::new("1234")),
+            OnceCallback::new(move |result| {
+                let _ = change_tx.send(result);
+            }),
+        );
+        change_rx.recv_timeout(Duration::from_secs(1)).unwrap().unwrap();
+
+        // The old PIN no longer unlocks the authenticator...
+        assert!(matches!(
+            register(&mut sm, [13u8; 32], Some(Pin::new("1234"))),
+            Err(Error::Pin(PinError::InvalidPin { .. }))
+        ));
+        // ...but the new one does.
+        assert!(register(&mut sm, [14u8; 32], Some(Pin::new("5678"))).is_ok());
+    }
+
+
+    #[test]
+    fn enumerate_and_delete_a_resident_credential() {
+        let mut sm = single_device("token-0");
+
+        let (set_tx, set_rx) = mpsc::channel();
+        sm.set_pin(
+            0,
+            Pin::new("1234"),
+            None,
+            OnceCallback::new(move |result| {
+                let _ = set_tx.send(result);
+            }),
+        );
+        set_rx.recv_timeout(Duration::from_secs(1)).unwrap().unwrap();
+
+        let options = MakeCredentialsOptions {
+            resident_key: true,
+            ..MakeCredentialsOptions::default()
+        };
+        let user = User {
+            id: vec![1, 2, 3],
+            name: "user@example.com".to_string(),
+            display_name: "Example User".to_string(),
+        };
+        let (status_tx, _status_rx) = mpsc::channel();
+        let (result_tx, result_rx) = mpsc::channel();
+        let captured: Arc<Mutex<Option<AttestationObject>>> = Arc::new(Mutex::new(None));
+        let captured_in_transform = captured.clone();
+        let params = MakeCredentials::new(
+            [15u8; 32],
+            RelyingParty::new("example.com"),
+            Some(user),
+            vec![PublicKeyCredentialParameters::ES256],
+            Vec::new(),
+            Some(options),
+            Some(Pin::new("1234")),
+        );
+        sm.register(
+            5000,
+            params,
+            status_tx,
+            OnceCallback::new(move |result: Result<::RegisterResult, Error>| {
+                let _ = result_tx.send(result.map(|_| ()));
+            })
+            .map(move |attestation_object: AttestationObject| {
+                *captured_in_transform.lock().unwrap() = Some(attestation_object);
+                Vec::new()
+            }),
+        );
+        result_rx.recv_timeout(Duration::from_secs(1)).unwrap().unwrap();
+        let credential_id = captured
+            .lock()
+            .unwrap()
+            .take()
+            .unwrap()
+            .auth_data
+            .credential_data
+            .unwrap()
+            .credential_id;
+
+        let map_to_result = |response: CredentialManagementResponse| match response {
+            CredentialManagementResponse::Credentials(credentials) => {
+                ::CredentialManagementResult { credentials }
+            }
+            CredentialManagementResponse::Deleted => ::CredentialManagementResult {
+                credentials: Vec::new(),
+            },
+        };
+
+        let (enumerate_tx, enumerate_rx) = mpsc::channel();
+        sm.credential_management(
+            0,
+            CredentialManagementRequest::EnumerateCredentials,
+            Some(Pin::new("1234")),
+            OnceCallback::new(move |result| {
+                let _ = enumerate_tx.send(result);
+            })
+            .map(map_to_result),
+        );
+        let enumerated = enumerate_rx
+            .recv_timeout(Duration::from_secs(1))
+            .unwrap()
+            .unwrap();
+        assert_eq!(enumerated.credentials.len(), 1);
+        assert_eq!(enumerated.credentials[0].1.len(), 1);
+        assert_eq!(enumerated.credentials[0].1[0].id, vec![1, 2, 3]);
+
+        let (delete_tx, delete_rx) = mpsc::channel();
+        sm.credential_management(
+            0,
+            CredentialManagementRequest::DeleteCredential(PublicKeyCredentialDescriptor {
+                id: credential_id,
+            }),
+            Some(Pin::new("1234")),
+            OnceCallback::new(move |result| {
+                let _ = delete_tx.send(result);
+            })
+            .map(map_to_result),
+        );
+        delete_rx.recv_timeout(Duration::from_secs(1)).unwrap().unwrap();
+
+        let (verify_tx, verify_rx) = mpsc::channel();
+        sm.credential_management(
+            0,
+            CredentialManagementRequest::EnumerateCredentials,
+            Some(Pin::new("1234")),
+            OnceCallback::new(move |result| {
+                let _ = verify_tx.send(result);
+            })
+            .map(map_to_result),
+        );
+        let after_delete = verify_rx
+            .recv_timeout(Duration::from_secs(1))
+            .unwrap()
+            .unwrap();
+        assert!(after_delete.credentials.is_empty());
+    }
+}
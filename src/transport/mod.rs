@@ -0,0 +1,93 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The boundary between `StateMachine` and the authenticators it drives.
+//!
+//! `platform` provides the one [`Device`] implementation this crate ships:
+//! an in-process, software-emulated CTAP2 authenticator that speaks the real
+//! `pinUvAuthProtocolOne` handshake and keeps a real credential store, so
+//! `StateMachine`'s logic is exercised the same way it would be against real
+//! hardware. No real USB/NFC/BLE transport is wired up yet — there's no CBOR
+//! codec in this tree, and `transport::platform::enumerate()` always hands
+//! back a software token rather than scanning for physical devices. A real
+//! transport only needs to implement [`Device`]; `StateMachine` and
+//! everything above it are transport-agnostic.
+
+pub mod platform;
+
+use crate::ctap2::attestation::AttestationObject;
+use crate::ctap2::commands::credential_management::{
+    CredentialManagementRequest, CredentialManagementResponse,
+};
+use crate::ctap2::commands::{AssertionObject, GetAssertion, MakeCredentials};
+use crate::Error;
+
+/// A single CTAP2 authenticator `StateMachine` can enumerate and drive.
+pub trait Device: Send + Sync {
+    /// A stable identifier suitable for `StatusUpdate::dev_info`.
+    fn id(&self) -> String;
+
+    /// Simulates the user touching this device during device selection.
+    fn touch(&self);
+
+    /// Non-blocking: whether this device has been touched since it started
+    /// a device-selection round.
+    fn is_touched(&self) -> bool;
+
+    /// Clears the touched latch `is_touched` reports, so a device that won
+    /// a previous selection round doesn't automatically win the next one.
+    fn reset_touch(&self);
+
+    /// `authenticatorClientPIN` `getKeyAgreement`: generates a fresh
+    /// per-session key-agreement keypair and returns its public key.
+    fn begin_pin_uv_auth(&self, platform_public_key: &[u8]) -> Vec<u8>;
+
+    /// `authenticatorClientPIN` `getPinToken`: validates `pin_hash_enc`
+    /// (encrypted under the shared secret from the most recent
+    /// `begin_pin_uv_auth`) and, if it matches, returns a fresh
+    /// `pinUvAuthToken`, itself encrypted under the same shared secret.
+    fn get_pin_token(&self, pin_hash_enc: &[u8]) -> Result<Vec<u8>, Error>;
+
+    /// `authenticatorClientPIN` `setPIN`.
+    fn set_pin(&self, new_pin_enc: &[u8], pin_uv_auth_param: &[u8; 16]) -> Result<(), Error>;
+
+    /// `authenticatorClientPIN` `changePIN`.
+    fn change_pin(
+        &self,
+        new_pin_enc: &[u8],
+        pin_hash_enc: &[u8],
+        pin_uv_auth_param: &[u8; 16],
+    ) -> Result<(), Error>;
+
+    fn make_credential(
+        &self,
+        req: &MakeCredentials,
+        pin_uv_auth_param: Option<&[u8; 16]>,
+    ) -> Result<AttestationObject, Error>;
+
+    fn get_assertion(
+        &self,
+        req: &GetAssertion,
+        pin_uv_auth_param: Option<&[u8; 16]>,
+    ) -> Result<AssertionObject, Error>;
+
+    /// `hmac-secret` (assertion side): decrypts `salt_enc` under the shared
+    /// secret from the most recent `begin_pin_uv_auth`, verifies
+    /// `salt_auth`, derives the output secret(s) for `credential_id`, and
+    /// returns them encrypted under the same shared secret.
+    fn hmac_secret(
+        &self,
+        credential_id: &[u8],
+        salt_enc: &[u8],
+        salt_auth: &[u8; 16],
+    ) -> Result<Vec<u8>, Error>;
+
+    fn credential_management(
+        &self,
+        req: &CredentialManagementRequest,
+        pin_uv_auth_param: &[u8; 16],
+    ) -> Result<CredentialManagementResponse, Error>;
+
+    fn reset(&self) -> Result<(), Error>;
+}
@@ -0,0 +1,555 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The one `Device` implementation this crate ships: an in-process,
+//! software-emulated CTAP2 authenticator. It speaks the real
+//! `pinUvAuthProtocolOne` handshake (see `crate::ctap2::commands::pin_protocol`)
+//! and keeps a real, in-memory credential store, so it can stand in for
+//! hardware in tests and in environments (like this one) without USB HID
+//! access.
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey, VerifyingKey};
+use p256::elliptic_curve::Generate;
+use sha2::{Digest, Sha256};
+
+use crate::ctap2::attestation::{
+    AttestationObject, AttestationStatement, AuthenticatorData, CredentialData,
+    CredentialPublicKey, FidoU2FAttestationStatement,
+};
+use crate::ctap2::commands::credential_management::{
+    CredentialManagementRequest, CredentialManagementResponse,
+};
+use crate::ctap2::commands::pin_protocol::{pin_hash, KeyAgreement, SharedSecret};
+use crate::ctap2::commands::{AssertionObject, GetAssertion, HmacSecretOutput, MakeCredentials};
+use crate::ctap2::server::{RelyingParty, User};
+use crate::transport::Device;
+use crate::{Error, PinError};
+
+/// A CTAP2 authenticator only accepts `authenticatorReset` within this long
+/// after it powered up.
+const RESET_WINDOW: Duration = Duration::from_secs(10);
+
+struct StoredCredential {
+    relying_party: RelyingParty,
+    user: Option<User>,
+    credential_id: Vec<u8>,
+    signing_key: SigningKey,
+    hmac_secret_key: Option<[u8; 32]>,
+}
+
+struct TokenState {
+    pin_hash: Option<[u8; 16]>,
+    retries: u8,
+    key_agreement: Option<(KeyAgreement, SharedSecret)>,
+    pin_uv_auth_token: Option<[u8; 32]>,
+    credentials: Vec<StoredCredential>,
+    sign_count: u32,
+}
+
+/// A software-emulated CTAP2 authenticator, usable directly as a [`Device`].
+pub struct SoftwareToken {
+    id: String,
+    powered_up_at: Instant,
+    state: Mutex<TokenState>,
+    touched: AtomicBool,
+}
+
+impl SoftwareToken {
+    pub fn new(id: impl Into<String>) -> Self {
+        SoftwareToken {
+            id: id.into(),
+            powered_up_at: Instant::now(),
+            state: Mutex::new(TokenState {
+                pin_hash: None,
+                retries: 8,
+                key_agreement: None,
+                pin_uv_auth_token: None,
+                credentials: Vec::new(),
+                sign_count: 0,
+            }),
+            touched: AtomicBool::new(false),
+        }
+    }
+
+    fn shared_secret(&self, state: &TokenState) -> Result<SharedSecret, Error> {
+        state
+            .key_agreement
+            .as_ref()
+            .map(|(_, secret)| secret.clone())
+            .ok_or(Error::Unknown)
+    }
+}
+
+impl Device for SoftwareToken {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    fn touch(&self) {
+        self.touched.store(true, Ordering::SeqCst);
+    }
+
+    fn is_touched(&self) -> bool {
+        self.touched.load(Ordering::SeqCst)
+    }
+
+    fn reset_touch(&self) {
+        self.touched.store(false, Ordering::SeqCst);
+    }
+
+    fn begin_pin_uv_auth(&self, platform_public_key: &[u8]) -> Vec<u8> {
+        let device_key = KeyAgreement::generate();
+        // ECDH is symmetric: this independently-derived secret is identical
+        // to the one the platform computes from our public key below.
+        let secret = device_key
+            .shared_secret(platform_public_key)
+            .expect("platform always sends a valid SEC1 public key");
+        let public_key = device_key.public_key_bytes();
+
+        let mut state = self.state.lock().unwrap();
+        state.key_agreement = Some((device_key, secret));
+        public_key
+    }
+
+    fn get_pin_token(&self, pin_hash_enc: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut state = self.state.lock().unwrap();
+        let secret = self.shared_secret(&state)?;
+
+        let Some(expected) = state.pin_hash else {
+            return Err(Error::Pin(PinError::PinRequired));
+        };
+        if state.retries == 0 {
+            return Err(Error::Pin(PinError::PinBlocked));
+        }
+
+        let decrypted = secret.decrypt(pin_hash_enc)?;
+        if decrypted.len() != 16 || decrypted[..] != expected[..] {
+            state.retries -= 1;
+            return Err(Error::Pin(PinError::InvalidPin {
+                retries: Some(state.retries),
+            }));
+        }
+        state.retries = 8;
+
+        let mut token = [0u8; 32];
+        rand::fill(&mut token[..]);
+        state.pin_uv_auth_token = Some(token);
+        Ok(secret.encrypt(&token))
+    }
+
+    fn set_pin(&self, new_pin_enc: &[u8], pin_uv_auth_param: &[u8; 16]) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        let secret = self.shared_secret(&state)?;
+        if !secret.verify(new_pin_enc, pin_uv_auth_param) {
+            return Err(Error::Unknown);
+        }
+        if state.pin_hash.is_some() {
+            // `setPIN` is only valid for the very first PIN; afterwards the
+            // authenticator requires `changePIN`.
+            return Err(Error::Unknown);
+        }
+        let padded = secret.decrypt(new_pin_enc)?;
+        state.pin_hash = Some(pin_hash_from_padded(&padded)?);
+        Ok(())
+    }
+
+    fn change_pin(
+        &self,
+        new_pin_enc: &[u8],
+        pin_hash_enc: &[u8],
+        pin_uv_auth_param: &[u8; 16],
+    ) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        let secret = self.shared_secret(&state)?;
+
+        let mut message = new_pin_enc.to_vec();
+        message.extend_from_slice(pin_hash_enc);
+        if !secret.verify(&message, pin_uv_auth_param) {
+            return Err(Error::Unknown);
+        }
+
+        let Some(expected) = state.pin_hash else {
+            return Err(Error::Pin(PinError::PinRequired));
+        };
+        if state.retries == 0 {
+            return Err(Error::Pin(PinError::PinBlocked));
+        }
+        let decrypted_hash = secret.decrypt(pin_hash_enc)?;
+        if decrypted_hash.len() != 16 || decrypted_hash[..] != expected[..] {
+            state.retries -= 1;
+            return Err(Error::Pin(PinError::InvalidPin {
+                retries: Some(state.retries),
+            }));
+        }
+        state.retries = 8;
+
+        let padded = secret.decrypt(new_pin_enc)?;
+        state.pin_hash = Some(pin_hash_from_padded(&padded)?);
+        Ok(())
+    }
+
+    fn make_credential(
+        &self,
+        req: &MakeCredentials,
+        pin_uv_auth_param: Option<&[u8; 16]>,
+    ) -> Result<AttestationObject, Error> {
+        let user_validation_required = req
+            .options
+            .as_ref()
+            .map(|options| options.user_validation)
+            .unwrap_or(false);
+        let mut state = self.state.lock().unwrap();
+        verify_user_verification(
+            &state,
+            &req.client_data_hash,
+            pin_uv_auth_param,
+            user_validation_required,
+        )?;
+
+        let signing_key = SigningKey::generate();
+        let verifying_key = VerifyingKey::from(&signing_key);
+        let mut credential_id = [0u8; 16];
+        rand::fill(&mut credential_id[..]);
+
+        let hmac_secret_key = if req
+            .options
+            .as_ref()
+            .map(|options| options.hmac_secret)
+            .unwrap_or(false)
+        {
+            let mut key = [0u8; 32];
+            rand::fill(&mut key[..]);
+            Some(key)
+        } else {
+            None
+        };
+
+        state.credentials.push(StoredCredential {
+            relying_party: req.relying_party.clone(),
+            user: req.user.clone(),
+            credential_id: credential_id.to_vec(),
+            signing_key: signing_key.clone(),
+            hmac_secret_key,
+        });
+        state.sign_count += 1;
+        let sign_count = state.sign_count;
+
+        let mut flags = 0x41; // user present (0x01) + attested credential data (0x40)
+        if pin_uv_auth_param.is_some() {
+            flags |= 0x04; // user verified
+        }
+
+        let auth_data = AuthenticatorData {
+            rp_id_hash: req.relying_party.hash,
+            flags,
+            counter: sign_count,
+            credential_data: Some(CredentialData {
+                credential_id: credential_id.to_vec(),
+                credential_public_key: CredentialPublicKey {
+                    bytes: verifying_key.to_sec1_bytes().to_vec(),
+                },
+            }),
+        };
+
+        let mut signed_over = auth_data.rp_id_hash.to_vec();
+        signed_over.push(auth_data.flags);
+        signed_over.extend_from_slice(&auth_data.counter.to_be_bytes());
+        signed_over.extend_from_slice(&credential_id);
+        signed_over.extend_from_slice(&req.client_data_hash);
+        let signature: Signature = signing_key.sign(&signed_over);
+
+        // This is a software token, not a certified hardware authenticator:
+        // there is no real attestation batch key or X.509 chain to present,
+        // so it self-attests with a placeholder certificate derived from
+        // its own identity rather than claiming to be a genuine device.
+        let placeholder_cert = Sha256::digest(self.id.as_bytes()).to_vec();
+
+        Ok(AttestationObject {
+            auth_data,
+            att_statement: AttestationStatement::FidoU2F(FidoU2FAttestationStatement {
+                attestation_cert: vec![placeholder_cert],
+                sig: signature.to_der().as_bytes().to_vec(),
+            }),
+        })
+    }
+
+    fn get_assertion(
+        &self,
+        req: &GetAssertion,
+        pin_uv_auth_param: Option<&[u8; 16]>,
+    ) -> Result<AssertionObject, Error> {
+        let user_validation_required = req
+            .options
+            .as_ref()
+            .map(|options| options.user_validation)
+            .unwrap_or(false);
+        let mut state = self.state.lock().unwrap();
+        verify_user_verification(
+            &state,
+            &req.client_data_hash,
+            pin_uv_auth_param,
+            user_validation_required,
+        )?;
+
+        let matches_allow_list = |credential: &StoredCredential| {
+            req.allow_list.is_empty()
+                || req
+                    .allow_list
+                    .iter()
+                    .any(|descriptor| descriptor.id == credential.credential_id)
+        };
+
+        let index = state
+            .credentials
+            .iter()
+            .position(|credential| {
+                credential.relying_party.hash == req.relying_party.hash
+                    && matches_allow_list(credential)
+            })
+            .ok_or(Error::Unknown)?;
+
+        state.sign_count += 1;
+        let sign_count = state.sign_count;
+        let credential = &state.credentials[index];
+
+        let mut flags = 0x01; // user present
+        if pin_uv_auth_param.is_some() {
+            flags |= 0x04; // user verified
+        }
+
+        let auth_data = AuthenticatorData {
+            rp_id_hash: req.relying_party.hash,
+            flags,
+            counter: sign_count,
+            credential_data: None,
+        };
+
+        let mut signed_over = auth_data.rp_id_hash.to_vec();
+        signed_over.push(auth_data.flags);
+        signed_over.extend_from_slice(&auth_data.counter.to_be_bytes());
+        signed_over.extend_from_slice(&req.client_data_hash);
+        let signature: Signature = credential.signing_key.sign(&signed_over);
+
+        Ok(AssertionObject {
+            credential_id: credential.credential_id.clone(),
+            auth_data,
+            signature: signature.to_der().as_bytes().to_vec(),
+            hmac_secret_output: None,
+        })
+    }
+
+    fn hmac_secret(
+        &self,
+        credential_id: &[u8],
+        salt_enc: &[u8],
+        salt_auth: &[u8; 16],
+    ) -> Result<Vec<u8>, Error> {
+        let state = self.state.lock().unwrap();
+        let secret = self.shared_secret(&state)?;
+        if !secret.verify(salt_enc, salt_auth) {
+            return Err(Error::Unknown);
+        }
+
+        let credential = state
+            .credentials
+            .iter()
+            .find(|credential| credential.credential_id == credential_id)
+            .ok_or(Error::Unknown)?;
+        let hmac_key = credential.hmac_secret_key.ok_or(Error::Unknown)?;
+
+        let salts = secret.decrypt(salt_enc)?;
+        let output = if salts.len() == 64 {
+            let mut out = derive_hmac_secret(&hmac_key, &salts[..32]).to_vec();
+            out.extend_from_slice(&derive_hmac_secret(&hmac_key, &salts[32..]));
+            out
+        } else if salts.len() == 32 {
+            derive_hmac_secret(&hmac_key, &salts).to_vec()
+        } else {
+            return Err(Error::Unknown);
+        };
+
+        Ok(secret.encrypt(&output))
+    }
+
+    fn credential_management(
+        &self,
+        req: &CredentialManagementRequest,
+        pin_uv_auth_param: &[u8; 16],
+    ) -> Result<CredentialManagementResponse, Error> {
+        let mut state = self.state.lock().unwrap();
+        let token = state.pin_uv_auth_token.ok_or(Error::Unknown)?;
+        let message = credential_management_message(req);
+        if !SharedSecret::from_bytes(token).verify(&message, pin_uv_auth_param) {
+            return Err(Error::Unknown);
+        }
+
+        match req {
+            CredentialManagementRequest::EnumerateCredentials => {
+                let mut by_rp: Vec<(RelyingParty, Vec<User>)> = Vec::new();
+                for credential in &state.credentials {
+                    let entry = by_rp
+                        .iter_mut()
+                        .find(|(rp, _)| rp.hash == credential.relying_party.hash);
+                    let user = match &credential.user {
+                        Some(user) => user.clone(),
+                        None => continue,
+                    };
+                    match entry {
+                        Some((_, users)) => users.push(user),
+                        None => by_rp.push((credential.relying_party.clone(), vec![user])),
+                    }
+                }
+                Ok(CredentialManagementResponse::Credentials(by_rp))
+            }
+            CredentialManagementRequest::DeleteCredential(descriptor) => {
+                let before = state.credentials.len();
+                state
+                    .credentials
+                    .retain(|credential| credential.credential_id != descriptor.id);
+                if state.credentials.len() == before {
+                    return Err(Error::Unknown);
+                }
+                Ok(CredentialManagementResponse::Deleted)
+            }
+        }
+    }
+
+    fn reset(&self) -> Result<(), Error> {
+        if self.powered_up_at.elapsed() > RESET_WINDOW {
+            return Err(Error::NotAllowed);
+        }
+        let mut state = self.state.lock().unwrap();
+        state.pin_hash = None;
+        state.retries = 8;
+        state.key_agreement = None;
+        state.pin_uv_auth_token = None;
+        state.credentials.clear();
+        state.sign_count = 0;
+        Ok(())
+    }
+}
+
+fn verify_user_verification(
+    state: &TokenState,
+    client_data_hash: &[u8; 32],
+    pin_uv_auth_param: Option<&[u8; 16]>,
+    user_validation_required: bool,
+) -> Result<(), Error> {
+    match (state.pin_uv_auth_token, pin_uv_auth_param) {
+        (_, None) => {
+            if user_validation_required {
+                Err(Error::Pin(PinError::PinRequired))
+            } else {
+                Ok(())
+            }
+        }
+        (Some(token), Some(param)) => {
+            if SharedSecret::from_bytes(token).verify(client_data_hash, param) {
+                Ok(())
+            } else {
+                Err(Error::Unknown)
+            }
+        }
+        (None, Some(_)) => Err(Error::Unknown),
+    }
+}
+
+fn pin_hash_from_padded(padded: &[u8]) -> Result<[u8; 16], Error> {
+    let end = padded.iter().position(|&b| b == 0).unwrap_or(padded.len());
+    if end == 0 {
+        return Err(Error::Unknown);
+    }
+    let pin = std::str::from_utf8(&padded[..end]).map_err(|_| Error::Unknown)?;
+    Ok(pin_hash(pin))
+}
+
+fn derive_hmac_secret(hmac_key: &[u8; 32], salt: &[u8]) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    let mut hasher = Sha256::new();
+    hasher.update(hmac_key);
+    hasher.update(salt);
+    hash.copy_from_slice(&hasher.finalize());
+    hash
+}
+
+fn credential_management_message(req: &CredentialManagementRequest) -> Vec<u8> {
+    match req {
+        CredentialManagementRequest::EnumerateCredentials => vec![0x01],
+        CredentialManagementRequest::DeleteCredential(descriptor) => {
+            let mut message = vec![0x02];
+            message.extend_from_slice(&descriptor.id);
+            message
+        }
+    }
+}
+
+/// Decrypts a `hmac_secret` extension response produced by
+/// [`Device::hmac_secret`] into the public [`HmacSecretOutput`] shape.
+pub fn decrypt_hmac_secret_output(
+    secret: &SharedSecret,
+    output_enc: &[u8],
+    two_salts: bool,
+) -> Result<HmacSecretOutput, Error> {
+    let output = secret.decrypt(output_enc)?;
+    if two_salts {
+        if output.len() != 64 {
+            return Err(Error::Unknown);
+        }
+        let mut one = [0u8; 32];
+        let mut two = [0u8; 32];
+        one.copy_from_slice(&output[..32]);
+        two.copy_from_slice(&output[32..]);
+        Ok(HmacSecretOutput::Two(one, two))
+    } else {
+        if output.len() != 32 {
+            return Err(Error::Unknown);
+        }
+        let mut one = [0u8; 32];
+        one.copy_from_slice(&output);
+        Ok(HmacSecretOutput::One(one))
+    }
+}
+
+thread_local! {
+    static ACTIVE_DEVICES: RefCell<Option<Vec<Arc<SoftwareToken>>>> = const { RefCell::new(None) };
+}
+
+/// Injects a fixed set of devices for the current thread, so tests can drive
+/// `StateMachine` against known `SoftwareToken`s instead of a fresh default
+/// one. `U2FManager::new()` reads this thread-local (re-propagated onto its
+/// background thread, since thread-locals don't cross threads) to decide
+/// which devices `StateMachine` should enumerate.
+pub struct TestCase;
+
+impl TestCase {
+    /// Takes the devices configured for the current thread, if any, so they
+    /// can be handed to a newly spawned thread via [`TestCase::activate`].
+    pub fn active() -> Option<Vec<Arc<SoftwareToken>>> {
+        ACTIVE_DEVICES.with(|cell| cell.borrow().clone())
+    }
+
+    /// Sets the devices `StateMachine::new()` should enumerate on the
+    /// current thread.
+    pub fn activate(devices: Option<Vec<Arc<SoftwareToken>>>) {
+        ACTIVE_DEVICES.with(|cell| *cell.borrow_mut() = devices);
+    }
+}
+
+/// The devices `StateMachine::new()` should drive: the thread-local test
+/// devices if any were configured, or a single fresh `SoftwareToken`
+/// otherwise.
+pub fn enumerate() -> Vec<Arc<dyn Device>> {
+    let devices = TestCase::active()
+        .unwrap_or_else(|| vec![Arc::new(SoftwareToken::new("software-token-0"))]);
+    devices
+        .into_iter()
+        .map(|device| device as Arc<dyn Device>)
+        .collect()
+}
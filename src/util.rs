@@ -0,0 +1,91 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::sync::{Arc, Mutex};
+
+use crate::Error;
+
+type BoxedCallback<T> = Box<dyn FnOnce(Result<T, Error>) + Send>;
+
+/// A `FnOnce` callback that can be cloned and handed to multiple in-flight
+/// device requests, only the first of which actually invokes it.
+pub struct OnceCallback<T> {
+    callback: Arc<Mutex<Option<BoxedCallback<T>>>>,
+}
+
+impl<T> OnceCallback<T>
+where
+    T: Send + 'static,
+{
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: FnOnce(Result<T, Error>) + Send + 'static,
+    {
+        OnceCallback {
+            callback: Arc::new(Mutex::new(Some(Box::new(callback)))),
+        }
+    }
+
+    /// Invokes the callback with `result`, if it hasn't already fired.
+    pub fn call(&self, result: Result<T, Error>) {
+        if let Some(callback) = self.callback.lock().unwrap().take() {
+            callback(result);
+        }
+    }
+
+    /// Wraps this callback with a transform applied ahead of its success
+    /// value, so device-layer types never have to leak into the manager's
+    /// public callback signature.
+    pub fn map<U, F>(self, transform: F) -> OnceCallbackMap<U, T>
+    where
+        F: Fn(U) -> T + Send + Sync + 'static,
+        U: Send + 'static,
+    {
+        OnceCallbackMap::new(self, transform)
+    }
+}
+
+impl<T> Clone for OnceCallback<T> {
+    fn clone(&self) -> Self {
+        OnceCallback {
+            callback: self.callback.clone(),
+        }
+    }
+}
+
+/// An `OnceCallback<U>` composed with a `T -> U` transform, so a device-layer
+/// response type `T` can be mapped into the public result type `U` right
+/// before the embedder's callback runs.
+pub struct OnceCallbackMap<T, U> {
+    inner: OnceCallback<U>,
+    transform: Arc<dyn Fn(T) -> U + Send + Sync>,
+}
+
+impl<T, U> OnceCallbackMap<T, U>
+where
+    U: Send + 'static,
+{
+    pub fn new<F>(inner: OnceCallback<U>, transform: F) -> Self
+    where
+        F: Fn(T) -> U + Send + Sync + 'static,
+    {
+        OnceCallbackMap {
+            inner,
+            transform: Arc::new(transform),
+        }
+    }
+
+    pub fn call(&self, result: Result<T, Error>) {
+        self.inner.call(result.map(|value| (self.transform)(value)));
+    }
+}
+
+impl<T, U> Clone for OnceCallbackMap<T, U> {
+    fn clone(&self) -> Self {
+        OnceCallbackMap {
+            inner: self.inner.clone(),
+            transform: self.transform.clone(),
+        }
+    }
+}